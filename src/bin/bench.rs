@@ -0,0 +1,95 @@
+extern crate starfield_render;
+extern crate nalgebra;
+
+use starfield_render as sf;
+use std::time::{Duration, Instant};
+
+use nalgebra::{
+    Vector4,
+    Vector3,
+    Vector2,
+    Rotate,
+    Rotation3
+};
+
+fn same(a: &sf::DepthBuffer<f32>, b: &sf::DepthBuffer<f32>) -> bool
+{
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let equal = match (a.get(x, y), b.get(x, y)) {
+                (&Some((va, da)), &Some((vb, db))) => va.to_bits() == vb.to_bits() && da.to_bits() == db.to_bits(),
+                (&None, &None) => true,
+                _ => false
+            };
+            if !equal {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn main()
+{
+    println!("TEST PROGRAM 4: TILED BENCHMARK");
+
+    let (width, height) = (400, 200);
+    let frames = 50;
+    let grid = 24;
+
+    let mut verts = Vec::new();
+    let mut patches = Vec::new();
+    for j in 0..(grid+1) {
+        for i in 0..(grid+1) {
+            verts.push(Vector2::new(2.0*i as f32/grid as f32 - 1.0, 2.0*j as f32/grid as f32 - 1.0));
+        }
+    }
+    for j in 0..grid {
+        for i in 0..grid {
+            let (a, b, c, d) = (j*(grid+1)+i, j*(grid+1)+i+1, (j+1)*(grid+1)+i, (j+1)*(grid+1)+i+1);
+            patches.push(sf::Patch::Tri(a, b, c));
+            patches.push(sf::Patch::Tri(d, c, b));
+            patches.push(sf::Patch::Tri(c, b, a));
+            patches.push(sf::Patch::Tri(b, c, d));
+            patches.push(sf::Patch::Line(a, d));
+            patches.push(sf::Patch::Point(a));
+        }
+    }
+
+    let vertex = |u: &f32, v: &Vector2<f32>| {
+        let p = Rotation3::new(Vector3::new(0.3, *u, 0.0)).rotate(&Vector3::new(v.x, v.y, 0.0));
+        (Vector4::new(p.x, p.y, p.z, p.z+1.5), v.clone())
+    };
+
+    let fragment = |u: &f32, v: &Vector2<f32>| {
+        Some((v.x*v.y*10.0 + u).sin())
+    };
+
+    let mut serial = sf::Buffer::new(width, height, None);
+    let mut tiled = sf::Buffer::new(width, height, None);
+    let tiling = sf::Tiling::default();
+
+    let mut serial_time = Duration::new(0, 0);
+    let mut tiled_time = Duration::new(0, 0);
+    let mut identical = true;
+    for frame in 0..frames {
+        let val = frame as f32 * 0.1;
+
+        let start = Instant::now();
+        serial.clear();
+        sf::process(&mut serial, &val, &verts, &patches, &vertex, &fragment);
+        serial_time += start.elapsed();
+
+        let start = Instant::now();
+        tiled.clear();
+        sf::process_tiled(&mut tiled, &tiling, &val, &verts, &patches, &vertex, &fragment);
+        tiled_time += start.elapsed();
+
+        identical = identical && same(&serial, &tiled);
+    }
+
+    println!("{} frames at {}x{}, {} patches", frames, width, height, patches.len());
+    println!("serial: {:?}", serial_time);
+    println!("tiled:  {:?} ({} threads, {} rows per tile)", tiled_time, tiling.threads, tiling.tile_height);
+    println!("identical: {}", identical);
+}
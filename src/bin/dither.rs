@@ -0,0 +1,58 @@
+extern crate starfield_render;
+extern crate nalgebra;
+
+use starfield_render as sf;
+
+use nalgebra::{
+    Vector3,
+    Vector2
+};
+
+fn main()
+{
+    println!("TEST PROGRAM 12: DITHER");
+
+    let (width, height) = (96, 6);
+    let verts = vec![Vector2::new(-1.0, -1.0), Vector2::new(1.0, -1.0), Vector2::new(-1.0, 1.0), Vector2::new(1.0, 1.0)];
+    let faces = vec![sf::Patch::Tri(0,1,2), sf::Patch::Tri(2,1,3)];
+    let vertex = |_: &(), v: &Vector2<f32>| {
+        (sf::transform(&sf::orthographic(2.0, 1.0, -1.0, 1.0), &Vector3::new(v.x, v.y, 0.0)), v.clone())
+    };
+
+    // A gray ramp from black to white, to be reduced to four characters.
+    let mut gray = sf::Buffer::new(width, height, None);
+    sf::process(&mut gray, &(), &verts, &faces, &vertex, |_: &(), v: &Vector2<f32>| {
+        Some(sf::Pixel::Grayscale((v.x + 1.0) / 2.0))
+    });
+
+    let methods = [
+        ("none", sf::Dither::None),
+        ("bayer 4x4", sf::Dither::Bayer4),
+        ("bayer 8x8", sf::Dither::Bayer8),
+        ("blue noise", sf::Dither::BlueNoise),
+        ("floyd-steinberg", sf::Dither::FloydSteinberg),
+        ("atkinson", sf::Dither::Atkinson)
+    ];
+    for &(name, method) in methods.iter() {
+        println!("{}:", name);
+        let chars = sf::Ditherer::new(method).quantize_chars(&gray, &[' ', '.', ':', '#']);
+        for y in (0..chars.height).rev() {
+            println!("{}", chars.row_iter(y).collect::<String>());
+        }
+    }
+
+    // A hue sweep reduced to the terminal palette.
+    let mut color = sf::Buffer::new(width, height, None);
+    sf::process(&mut color, &(), &verts, &faces, &vertex, |_: &(), v: &Vector2<f32>| {
+        let h = (v.x + 1.0) * 3.0;
+        let channel = |offset: f32| (1.0 - ((h - offset + 3.0) % 6.0 - 3.0).abs().min(2.0).max(1.0) + 1.0) * (v.y + 1.0) / 2.0;
+        Some(sf::Pixel::Color(channel(0.0), channel(2.0), channel(4.0)))
+    });
+    for &(name, method) in methods.iter() {
+        println!("{}:", name);
+        let codes = sf::Ditherer::new(method).quantize_256(&color);
+        for y in (0..codes.height).rev() {
+            println!("{}\x1B[0m", sf::make_colorstring(codes.row_iter(y).map(|&c| sf::ColorChar(7, c, ' '))));
+        }
+    }
+}
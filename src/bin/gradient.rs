@@ -4,7 +4,7 @@ extern crate nalgebra;
 use starfield_render as sf;
 
 use nalgebra::{
-    Vector4,
+    Vector3,
     Vector2
 };
 
@@ -25,7 +25,9 @@ fn main()
 {
     println!("TEST PROGRAM 2: GRADIENT");
 
-    let (width, height) = (100, 50);
+    let display = sf::Display::new(sf::Presenter::FullCell);
+    let (width, height) = display.buffer_size(100, 50);
+    let projection = display.orthographic(width, height, 2.0, -1.0, 1.0);
 
     let verts = vec![Vector2::new(-1.0, -1.0), Vector2::new(1.0, -1.0), Vector2::new(-1.0, 1.0), Vector2::new(1.0, 1.0)];
     let faces = vec![sf::Patch::Tri(0,1,2), sf::Patch::Tri(2,1,3)];
@@ -33,7 +35,7 @@ fn main()
     let mut buffer = sf::Buffer::new(width,height,None);
 
     let vertex = |u: &f32, v: &Vector2<f32>| {
-        (Vector4::new(v.x, v.y, 0.0, 1.0), v.clone())
+        (sf::transform(&projection, &Vector3::new(v.x, v.y, 0.0)), v.clone())
     };
 
     let fragment = |u: &f32, v: &Vector2<f32>| {
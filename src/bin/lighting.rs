@@ -0,0 +1,83 @@
+extern crate starfield_render;
+extern crate nalgebra;
+
+use starfield_render as sf;
+
+use nalgebra::{
+    Vector3,
+    Matrix4
+};
+
+fn print_mat(buf: &sf::DepthBuffer<sf::Pixel>)
+{
+    for y in (0..buf.height).rev() {
+        for x in 0..buf.width {
+            match buf.get(x,y) {
+                &Some((ref col, _)) => print!("\x1B[48;5;{}m ", sf::to_256_color(col, x, y)),
+                &None => print!("\x1B[48;5;0m ")
+            }
+        }
+        println!("\x1B[0m");
+    }
+}
+
+fn main()
+{
+    println!("TEST PROGRAM 10: LIGHTING");
+
+    let display = sf::Display::new(sf::Presenter::FullCell);
+    let (width, height) = display.buffer_size(120, 40);
+    let projection = display.perspective(width, height, 0.7, 0.1, 100.0);
+    let eye = Vector3::new(0.0, 0.0, 8.0);
+    let view_projection = projection * sf::look_at(&eye, &Vector3::new(0.0, 0.0, 0.0), &Vector3::new(0.0, 1.0, 0.0));
+
+    let sphere = sf::icosphere(1.0, 3);
+    let plastic = sf::Material {
+        name: "plastic".to_string(),
+        ambient: sf::Pixel::Color(0.02, 0.02, 0.05),
+        diffuse: sf::Pixel::Color(0.3, 0.4, 0.9),
+        specular: sf::Pixel::Color(0.6, 0.6, 0.6),
+        shininess: 40.0,
+        opacity: 1.0,
+        diffuse_map: None
+    };
+    let gold = sf::PbrMaterial {
+        albedo: sf::Pixel::Color(1.0, 0.77, 0.34),
+        metallic: 1.0,
+        roughness: 0.35
+    };
+
+    let mut buffer = sf::Buffer::new(width, height, None);
+    let mut time: f32 = 0.0;
+    loop {
+        time += 0.03;
+        let lights = [
+            sf::Light::Point {
+                position: Vector3::new(6.0 * time.cos(), 2.0, 6.0 * time.sin()),
+                color: sf::Pixel::Grayscale(40.0)
+            },
+            sf::Light::Directional {
+                direction: Vector3::new(0.0, -1.0, -0.3),
+                color: sf::Pixel::Color(0.2, 0.15, 0.1)
+            }
+        ];
+        buffer.clear();
+        // Left to right: Lambert, Blinn-Phong and PBR.
+        for (i, offset) in [-2.5f32, 0.0, 2.5].iter().enumerate() {
+            let vertex = |u: &Matrix4<f32>, v: &sf::MeshVertex| {
+                let position = v.position + Vector3::new(*offset, 0.0, 0.0);
+                (sf::transform(u, &position), sf::Surface { position: position, normal: v.normal })
+            };
+            let fragment = |_: &Matrix4<f32>, s: &sf::Surface| {
+                Some(match i {
+                    0 => sf::shade_lambert(s, &lights, sf::Pixel::Color(0.9, 0.3, 0.3), sf::Pixel::Grayscale(0.03)),
+                    1 => sf::shade_blinn_phong(s, &eye, &lights, &plastic),
+                    _ => sf::shade_pbr(s, &eye, &lights, &gold, sf::Pixel::Grayscale(0.03))
+                })
+            };
+            sphere.draw(&mut buffer, &view_projection, &vertex, &fragment);
+        }
+        print_mat(&buffer);
+        println!("\x1B[{}A", height+1);
+    }
+}
@@ -2,9 +2,9 @@ extern crate starfield_render;
 extern crate nalgebra;
 
 use starfield_render as sf;
+use std::f32;
 
 use nalgebra::{
-    Vector4,
     Vector3,
     Vector2,
     dot,
@@ -12,12 +12,12 @@ use nalgebra::{
     Rotation3
 };
 
-fn print_mat(buf: &sf::DepthBuffer<sf::Pixel>)
+fn print_mat(buf: &sf::DepthBuffer<u8>)
 {
     for y in (0..buf.height).rev() {
-        println!("{}", sf::make_colorstring(buf.row_iter(y).enumerate().map(|(x, p)| {
+        println!("{}", sf::make_colorstring(buf.row_iter(y).map(|p| {
             sf::ColorChar(7, match p {
-                &Some((ref val, _)) => sf::to_256_color(val, x, y),
+                &Some((val, _)) => val,
                 _ => 0
             }, ' ')
         })));
@@ -40,19 +40,23 @@ fn main()
 
     let mut buffer = sf::Buffer::new(width,height,None);
 
+    let aspect = sf::viewport_aspect(width, height, sf::CELL_ASPECT);
+    let camera = sf::perspective(f32::consts::FRAC_PI_2, aspect, 0.1, 10.0) * sf::look_at(&Vector3::new(0.0, 0.0, 1.5), &Vector3::new(0.0, 0.0, 0.0), &Vector3::new(0.0, 1.0, 0.0));
+
     let vertex = |u: &f32, v: &Vector2<f32>| {
         let p = Rotation3::new(Vector3::new(0.0, *u, 0.0)).rotate(&Vector3::new(v.x, v.y, 0.0));
-        (Vector4::new(p.x, p.y, p.z, p.z+1.5), v.clone())
+        (sf::transform(&camera, &p), v.clone())
     };
 
-    let fragment = |u: &f32, v: &Vector2<f32>| {
+    let fragment = sf::WithInfo(|u: &f32, v: &Vector2<f32>, info: &sf::FragmentInfo<Vector2<f32>>| {
         let v = v.x.hypot(v.y);
-        if v < 1.0 {
-            Some(sf::Pixel::Grayscale((0.5+u.cos()/2.0)*(1.0 - v)))
+        let pixel = if v < 1.0 {
+            sf::Pixel::Grayscale((0.5+u.cos()/2.0)*(1.0 - v))
         } else {
-            Some(sf::Pixel::Grayscale(0.5))
-        }
-    };
+            sf::Pixel::Grayscale(0.5)
+        };
+        Some(sf::to_256_color(&pixel, info.x, info.y))
+    });
 
     let mut val = 0.0;
 
@@ -0,0 +1,95 @@
+extern crate starfield_render;
+extern crate nalgebra;
+
+use starfield_render as sf;
+
+use nalgebra::{
+    Vector4,
+    Vector3,
+    Rotate,
+    Rotation3
+};
+
+fn print_mat(buf: &sf::DepthBuffer<u8>)
+{
+    for y in (0..buf.height).rev() {
+        println!("{}", sf::make_colorstring(buf.row_iter(y).map(|p| {
+            sf::ColorChar(7, match p {
+                &Some((val, _)) => val,
+                _ => 0
+            }, ' ')
+        })));
+    }
+}
+
+// Both windings of the quad a, b, c, d, so it can be seen from either side.
+fn quad(patches: &mut Vec<sf::Patch>, a: usize, b: usize, c: usize, d: usize)
+{
+    patches.push(sf::Patch::Tri(a, b, c));
+    patches.push(sf::Patch::Tri(a, c, d));
+    patches.push(sf::Patch::Tri(c, b, a));
+    patches.push(sf::Patch::Tri(d, c, a));
+}
+
+// The light looks straight down on the scene.
+fn light_clip(p: &Vector3<f32>) -> Vector4<f32>
+{
+    Vector4::new(p.x / 2.5, p.z / 2.5, p.y, 1.0)
+}
+
+fn main()
+{
+    println!("TEST PROGRAM 6: SHADOW");
+
+    let (width, height) = (100, 50);
+
+    // (position, whether it belongs to the spinning occluder)
+    let verts = vec![
+        (Vector3::new(-2.0, 0.0, -2.0), false),
+        (Vector3::new(2.0, 0.0, -2.0), false),
+        (Vector3::new(2.0, 0.0, 2.0), false),
+        (Vector3::new(-2.0, 0.0, 2.0), false),
+        (Vector3::new(-0.7, 1.0, -0.4), true),
+        (Vector3::new(0.7, 1.0, -0.4), true),
+        (Vector3::new(0.7, 1.0, 0.4), true),
+        (Vector3::new(-0.7, 1.0, 0.4), true)];
+    let mut patches = Vec::new();
+    quad(&mut patches, 0, 1, 2, 3);
+    quad(&mut patches, 4, 5, 6, 7);
+
+    let world = |u: &f32, v: &(Vector3<f32>, bool)| {
+        if v.1 {
+            Rotation3::new(Vector3::new(0.0, *u, 0.0)).rotate(&v.0)
+        } else {
+            v.0
+        }
+    };
+
+    // Tilt the scene towards the camera, which sits 5 units back.
+    let vertex = |u: &f32, v: &(Vector3<f32>, bool)| {
+        let p = world(u, v);
+        let (s, c) = (0.6f32.sin(), 0.6f32.cos());
+        let (y, z) = (p.y*c - p.z*s, p.y*s + p.z*c);
+        (Vector4::new(2.0*p.x, 2.0*(y - 0.5), z, 5.0 - z), p)
+    };
+
+    let mut shadow = sf::ShadowMap::new(64, 64);
+
+    let mut buffer = sf::Buffer::new(width, height, None);
+    let mut val = 0.0;
+    loop {
+        val += 0.01;
+        shadow.clear();
+        shadow.render(&val, &verts, &patches, |u: &f32, v: &(Vector3<f32>, bool)| light_clip(&world(u, v)));
+
+        let fragment = sf::WithInfo(|_: &f32, p: &Vector3<f32>, info: &sf::FragmentInfo<Vector3<f32>>| {
+            let albedo = if p.y > 0.5 { 0.9 } else { 0.6 };
+            let lit = shadow.visibility(&light_clip(p));
+            Some(sf::to_256_color(&sf::Pixel::Grayscale(albedo*(0.25 + 0.75*lit)), info.x, info.y))
+        });
+        buffer.clear();
+        sf::process(&mut buffer, &val, &verts, &patches, &vertex, &fragment);
+        print_mat(&buffer);
+        println!("\x1B[{}A", height+1);
+    }
+}
@@ -0,0 +1,63 @@
+extern crate starfield_render;
+
+use starfield_render as sf;
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn print_mat(buf: &sf::DepthBuffer<sf::Pixel>)
+{
+    for y in (0..buf.height).rev() {
+        for x in 0..buf.width {
+            match buf.get(x,y) {
+                &Some((ref col, _)) => print!("\x1B[48;5;{}m ", sf::to_256_color(col, x, y)),
+                &None => print!("\x1B[48;5;0m ")
+            }
+        }
+        println!("\x1B[0m");
+    }
+}
+
+// Usage: sky [catalog.csv [latitude longitude]]
+// Without a catalog, a random sky is shown instead.
+fn main()
+{
+    println!("TEST PROGRAM 8: SKY");
+
+    let args: Vec<String> = env::args().collect();
+    let latitude = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(51.48);
+    let longitude = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(0.0);
+
+    let mut stars = sf::Starfield::new(8);
+    stars.falloff = 0.0;
+    match args.get(1) {
+        Some(path) => {
+            stars.stars = match sf::load_catalog(path, stars.faintest) {
+                Ok(stars) => stars,
+                Err(err) => {
+                    println!("{}: {}", path, err);
+                    return;
+                }
+            };
+        },
+        None => stars.generate(&sf::Distribution::Shell(1.0), 4000)
+    }
+
+    let display = sf::Display::new(sf::Presenter::FullCell);
+    let (width, height) = display.buffer_size(100, 50);
+    let projection = sf::SkyProjection::Dome(display.aspect(width, height));
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as f64).unwrap_or(0.0);
+    let mut sky = sf::Sky::new(latitude, longitude, sf::unix_to_julian(now));
+
+    let mut buffer = sf::Buffer::new(width, height, None);
+    let mut time = 0.0;
+    loop {
+        // A minute of sky per frame.
+        sky.julian_date += 1.0 / 1440.0;
+        time += 0.02;
+        buffer.clear();
+        sky.render(&stars, &mut buffer, &projection, time);
+        print_mat(&buffer);
+        println!("\x1B[{}A", height+1);
+    }
+}
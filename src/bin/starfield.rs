@@ -0,0 +1,50 @@
+extern crate starfield_render;
+extern crate nalgebra;
+
+use starfield_render as sf;
+
+use nalgebra::Vector3;
+
+fn print_mat(buf: &sf::DepthBuffer<sf::Pixel>)
+{
+    for y in (0..buf.height).rev() {
+        for x in 0..buf.width {
+            match buf.get(x,y) {
+                &Some((ref col, _)) => print!("\x1B[48;5;{}m ", sf::to_256_color(col, x, y)),
+                &None => print!("\x1B[48;5;0m ")
+            }
+        }
+        println!("\x1B[0m");
+    }
+}
+
+fn main()
+{
+    println!("TEST PROGRAM 7: STARFIELD");
+
+    let display = sf::Display::new(sf::Presenter::FullCell);
+    let (width, height) = display.buffer_size(100, 50);
+    let projection = display.perspective(width, height, 1.0, 0.1, 100.0);
+
+    // Stars are generated as seen from a distance of 1, and the camera is much further away.
+    let mut stars = sf::Starfield::new(1);
+    stars.faintest = 2.0;
+    stars.limit = 9.0;
+    stars.generate(&sf::Distribution::Disk(8.0, 0.6), 3000);
+    stars.generate(&sf::Distribution::Cluster(Vector3::new(3.0, 1.0, -2.0), 0.4), 200);
+    stars.generate(&sf::Distribution::Sphere(20.0), 500);
+
+    let mut camera = sf::OrbitCamera::new(Vector3::new(0.0, 0.0, 0.0), 12.0);
+    camera.orbit(0.0, 0.35);
+
+    let mut buffer = sf::Buffer::new(width, height, None);
+    let mut time = 0.0;
+    loop {
+        time += 0.02;
+        camera.orbit(0.002, 0.0);
+        buffer.clear();
+        stars.render(&mut buffer, &(projection * camera.view()), &camera.eye(), time);
+        print_mat(&buffer);
+        println!("\x1B[{}A", height+1);
+    }
+}
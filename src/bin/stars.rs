@@ -9,6 +9,7 @@ use std::f32;
 use nalgebra::{
     Vector3,
     Vector4,
+    Matrix4,
     Rotation3
 };
 
@@ -32,13 +33,13 @@ fn main() -> ()
 
     let (width, height) = (100, 50);
 
-    let mut patches = Vec::new();
+    let star = vec![Vector4::new(0.0, 0.0, 0.0, 1.0)];
+    let patches = vec![sf::Patch::Point(0)];
     let mut verts: Vec<Vector4<f32>> = Vec::new();
 
     let mut rng = rand::thread_rng();
     let mut range = Range::new(-0.5, 0.5);
-    for i in 0..100 {
-        patches.push(sf::Patch::Point(i));
+    for _ in 0..100 {
         verts.push(Vector4::new(range.ind_sample(&mut rng), range.ind_sample(&mut rng), range.ind_sample(&mut rng), 0.0));
     }
     let mut range = Range::new(0, verts.len());
@@ -53,26 +54,32 @@ fn main() -> ()
 
     let mut buffer = sf::Buffer::new(width, height, None);
 
-    let vertex = |u: &f32, v: &Vector4<f32>| {
-        (Vector4::new(v.x * u.cos() + v.z* u.sin(), v.y, v.z * u.cos() - v.x* u.sin(), 1.0), 0.0)
+    let projection = sf::orthographic(2.0, sf::viewport_aspect(width, height, sf::CELL_ASPECT), 0.1, 10.0);
+    let mut camera = sf::OrbitCamera::new(Vector3::new(0.0, 0.0, 0.0), 2.0);
+
+    let vertex = |u: &Matrix4<f32>, v: &Vector4<f32>| {
+        (sf::transform(u, &Vector3::new(v.x, v.y, v.z)), 0.0)
+    };
+    let vertex_instanced = |u: &Matrix4<f32>, s: &Vector4<f32>, v: &Vector4<f32>, _: usize| {
+        vertex(u, &(*s + *v))
     };
 
-    let fragment = |u: &f32, v: &f32| {
+    let fragment = |u: &Matrix4<f32>, v: &f32| {
         Some('X')
     };
-    let fragment_dots = |u: &f32, v: &f32| {
+    let fragment_dots = |u: &Matrix4<f32>, v: &f32| {
         Some(':')
     };
-    let fragment_points = |u: &f32, v: &f32| {
+    let fragment_points = |u: &Matrix4<f32>, v: &f32| {
         Some('.')
     };
 
-    let mut val = 0.0;
     loop {
-        val += 0.001;
+        camera.orbit(0.001, 0.0);
+        let val = projection * camera.view();
         print_buffer(&buffer);
         buffer.clear();
-        sf::process(&mut buffer, &val, &verts, &patches, &vertex, &fragment);
+        sf::process_instanced(&mut buffer, &val, &star, &verts, &patches, &vertex_instanced, &fragment);
         //sf::process(&mut buffer, &val, &verts, &lines, &vertex, &fragment_dots);
         sf::process(&mut buffer, &val, &verts_tri, &tris, &vertex, &fragment_dots);
         println!("\x1B[{}A", height+1);
@@ -0,0 +1,74 @@
+extern crate starfield_render;
+extern crate nalgebra;
+
+use starfield_render as sf;
+
+use nalgebra::{
+    Vector4,
+    Vector3,
+    Vector2,
+    Rotate,
+    Rotation3
+};
+
+fn print_mat(buf: &sf::DepthBuffer<u8>)
+{
+    for y in (0..buf.height).rev() {
+        println!("{}", sf::make_colorstring(buf.row_iter(y).map(|p| {
+            sf::ColorChar(7, match p {
+                &Some((val, _)) => val,
+                _ => 0
+            }, ' ')
+        })));
+    }
+}
+
+fn main()
+{
+    println!("TEST PROGRAM 5: TEXTURE");
+
+    let (width, height) = (100, 50);
+
+    let mut checker = sf::Buffer::new(64, 64, sf::Pixel::Grayscale(0.0));
+    for y in 0..64 {
+        for x in 0..64 {
+            if (x/8 + y/8) % 2 == 0 {
+                checker.set(x, y, sf::Pixel::Grayscale(0.9));
+            }
+        }
+    }
+    let mip = sf::Mipmap::new(sf::Texture::new(checker));
+    let mut sampler = sf::Sampler::new(sf::Filter::Bilinear, sf::Wrap::Repeat, sf::Pixel::Grayscale(0.0));
+    sampler.max_anisotropy = 4;
+
+    let verts = vec![
+        Vector2::new(-1.0, -1.0),
+        Vector2::new(1.0, -1.0),
+        Vector2::new(-1.0, 1.0),
+        Vector2::new(1.0, 1.0)];
+
+    let patches = vec![sf::Patch::Tri(0,1,2), sf::Patch::Tri(3,2,1), sf::Patch::Tri(2,1,0), sf::Patch::Tri(1,2,3)];
+
+    let mut buffer = sf::Buffer::new(width,height,None);
+
+    // A floor seen at a grazing angle, slowly turning.
+    let vertex = |u: &f32, v: &Vector2<f32>| {
+        let p = Rotation3::new(Vector3::new(0.0, *u, 0.0)).rotate(&Vector3::new(v.x*4.0, 0.0, v.y*4.0));
+        (Vector4::new(p.x, p.y - 0.5, -p.z, p.z+4.5), (v.clone() + Vector2::new(1.0, 1.0)) * 4.0)
+    };
+
+    let fragment = sf::WithInfo(|_: &f32, uv: &Vector2<f32>, info: &sf::FragmentInfo<Vector2<f32>>| {
+        let pixel = sampler.sample_grad(&mip, uv, &info.dfdx(), &info.dfdy());
+        Some(sf::to_256_color(&pixel, info.x, info.y))
+    });
+
+    let mut val = 0.0;
+
+    loop {
+        val += 0.005;
+        buffer.clear();
+        sf::process(&mut buffer, &val, &verts, &patches, &vertex, &fragment);
+        print_mat(&buffer);
+        println!("\x1B[{}A", height+1);
+    }
+}
@@ -0,0 +1,40 @@
+extern crate starfield_render;
+
+use starfield_render as sf;
+
+fn print_mat(buf: &sf::DepthBuffer<sf::Pixel>)
+{
+    for y in (0..buf.height).rev() {
+        for x in 0..buf.width {
+            match buf.get(x,y) {
+                &Some((ref col, _)) => print!("\x1B[48;5;{}m ", sf::to_256_color(col, x, y)),
+                &None => print!("\x1B[48;5;0m ")
+            }
+        }
+        println!("\x1B[0m");
+    }
+}
+
+fn main()
+{
+    println!("TEST PROGRAM 9: WARP");
+
+    let display = sf::Display::new(sf::Presenter::FullCell);
+    let (width, height) = display.buffer_size(100, 50);
+    let projection = display.perspective(width, height, 1.2, 0.1, 100.0);
+
+    let mut warp = sf::Warp::new(9, 600);
+    let mut buffer = sf::Buffer::new(width, height, None);
+    let dt = 1.0 / 30.0;
+    let mut time: f32 = 0.0;
+    loop {
+        time += dt;
+        // Surge in and out of hyperspace.
+        warp.speed = 10.0 + 30.0 * (0.5 - 0.5 * (time * 0.5).cos());
+        warp.update(dt);
+        buffer.clear();
+        warp.render(&mut buffer, &projection);
+        print_mat(&buffer);
+        println!("\x1B[{}A", height+1);
+    }
+}
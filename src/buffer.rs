@@ -1,5 +1,7 @@
+use render::RenderError;
 use std::slice;
 
+#[derive(Copy, Clone, Debug)]
 pub struct Rect{
     pub x: usize,
     pub y: usize,
@@ -18,16 +20,38 @@ pub struct Buffer<T>
 
 fn distribute(size: usize, pos: f32) -> i32
 {
-    ((pos * size as f32) + 1.0) as i32 - 1
+    (((pos * size as f32) + 1.0) as i32).saturating_sub(1)
 }
 
-impl <T: Copy> Buffer<T>
+fn ratio_to_xy(width: usize, height: usize, x: f32, y: f32) -> Option<(usize, usize)>
+{
+    let ix = distribute(width, x);
+    let iy = distribute(height, y);
+    if 0 <= ix && ix < width as i32 && 0 <= iy && iy < height as i32 {
+        Some((ix as usize, iy as usize))
+    } else {
+        None
+    }
+}
+
+fn depth_apply<T>(slot: &mut Option<(T, f32)>, (val, depth): (T, f32)) -> ()
+{
+    if let &mut Some((_, d)) = slot {
+        if depth > d {
+            *slot = Some((val, depth));
+        }
+    } else {
+        *slot = Some((val, depth));
+    }
+}
+
+impl <T: Clone> Buffer<T>
 {
     pub fn new(width: usize, height: usize, val: T) -> Buffer<T>
     {
         let mut buf = Vec::with_capacity(width*height);
         for i in 0..(width*height) {
-            buf.push(val);
+            buf.push(val.clone());
         }
         Buffer {
             width: width,
@@ -38,7 +62,7 @@ impl <T: Copy> Buffer<T>
     pub fn fill(&mut self, val: T) -> ()
     {
         for i in 0..self.buf.len() {
-            self.buf[i] = val;
+            self.buf[i] = val.clone();
         }
     }
 }
@@ -56,13 +80,7 @@ impl <T> Buffer<T>
     }
     pub fn ratio_to_xy(&self, x: f32, y: f32) -> Option<(usize, usize)>
     {
-        let ix = distribute(self.width, x);
-        let iy = distribute(self.height, y);
-        if 0 <= ix && ix < self.width as i32 && 0 <= iy && iy < self.height as i32 {
-            Some((ix as usize, iy as usize))
-        } else {
-            None
-        }
+        ratio_to_xy(self.width, self.height, x, y)
     }
     pub fn center_to_xy(&self, x: f32, y: f32) -> Option<(usize, usize)>
     {
@@ -72,6 +90,26 @@ impl <T> Buffer<T>
     {
         &self.buf[self.get_index(x, y)]
     }
+    /// Like `get`, but `None` outside of the buffer.
+    pub fn try_get(&self, x: usize, y: usize) -> Option<&T>
+    {
+        if x < self.width && y < self.height {
+            Some(self.get(x, y))
+        } else {
+            None
+        }
+    }
+    /// Like `set`, but fails outside of the buffer instead of panicking or wrapping onto another
+    /// row.
+    pub fn try_set(&mut self, x: usize, y: usize, val: T) -> Result<(), RenderError>
+    {
+        if x < self.width && y < self.height {
+            self.set(x, y, val);
+            Ok(())
+        } else {
+            Err(RenderError::OutOfBounds(x, y))
+        }
+    }
     pub fn row_iter<'a>(&'a self, y: usize) -> slice::Iter<'a, T>
     {
         self.buf[y*self.width .. (y+1)*self.width].iter()
@@ -91,16 +129,10 @@ pub type DepthBuffer<T> = Buffer<Option<(T, f32)>>;
 
 impl <T> DepthBuffer<T>
 {
-    pub fn apply(&mut self, x: usize, y:usize , (val, depth): (T, f32)) -> ()
+    pub fn apply(&mut self, x: usize, y:usize , val: (T, f32)) -> ()
     {
         let index = self.get_index(x, y);
-        if let Some((_, d)) = self.buf[index] {
-            if depth > d {
-                self.buf[index] = Some((val, depth));
-            }
-        } else {
-            self.buf[index] = Some((val, depth));
-        }
+        depth_apply(&mut self.buf[index], val);
     }
     pub fn clear(&mut self) -> ()
     {
@@ -108,4 +140,67 @@ impl <T> DepthBuffer<T>
             self.buf[i] = None;
         }
     }
+    /// Borrows the whole buffer as a single tile.
+    pub fn tile<'a>(&'a mut self) -> Tile<'a, T>
+    {
+        let rect = self.get_rect();
+        Tile {
+            width: self.width,
+            height: self.height,
+            rect: rect,
+            buf: &mut self.buf[..]
+        }
+    }
+    /// Splits the buffer into horizontal bands of `rows` rows each (the last may be shorter).
+    pub fn tiles<'a>(&'a mut self, rows: usize) -> Vec<Tile<'a, T>>
+    {
+        let (width, height) = (self.width, self.height);
+        let rows = rows.max(1);
+        if width == 0 {
+            return Vec::new();
+        }
+        self.buf.chunks_mut(width*rows).enumerate().map(|(i, chunk)| {
+            Tile {
+                width: width,
+                height: height,
+                rect: Rect {
+                    x: 0,
+                    y: i*rows,
+                    w: width,
+                    h: chunk.len() / width
+                },
+                buf: chunk
+            }
+        }).collect()
+    }
+}
+
+/// A mutable window onto a band of rows of a `DepthBuffer`. Coordinates are those of the whole buffer,
+/// and only pixels within `rect` may be written.
+pub struct Tile<'a, T: 'a>
+{
+    pub width: usize,
+    pub height: usize,
+    pub rect: Rect,
+    buf: &'a mut [Option<(T, f32)>]
+}
+
+impl <'a, T> Tile<'a, T>
+{
+    pub fn contains(&self, x: usize, y: usize) -> bool
+    {
+        self.rect.x <= x && x < self.rect.x + self.rect.w && self.rect.y <= y && y < self.rect.y + self.rect.h
+    }
+    pub fn center_to_xy(&self, x: f32, y: f32) -> Option<(usize, usize)>
+    {
+        ratio_to_xy(self.width, self.height, (x+1.0)/2.0, (y+1.0)/2.0)
+    }
+    /// Like `DepthBuffer::apply`, but writes outside of `rect` are discarded.
+    pub fn apply(&mut self, x: usize, y: usize, val: (T, f32)) -> ()
+    {
+        if self.contains(x, y) {
+            let index = self.width * (y - self.rect.y) + x;
+            depth_apply(&mut self.buf[index], val);
+        }
+    }
 }
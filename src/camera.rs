@@ -0,0 +1,167 @@
+extern crate nalgebra;
+
+use nalgebra::{Matrix4, Vector3, Vector4, Norm, dot, cross};
+
+/// Width of a terminal character cell divided by its height. Most fonts are about twice as tall
+/// as they are wide.
+pub const CELL_ASPECT: f32 = 0.5;
+
+/// The displayed aspect ratio (width over height) of a `width` x `height` buffer whose pixels
+/// are `cell_aspect` times as wide as they are tall.
+pub fn viewport_aspect(width: usize, height: usize, cell_aspect: f32) -> f32
+{
+    width as f32 * cell_aspect / height as f32
+}
+
+// The rasterizer keeps the fragment with the largest depth, so unlike OpenGL these projections
+// map the near plane to z/w = 1 and the far plane to z/w = -1.
+
+/// A perspective projection with vertical field of view `fovy` (in radians), looking down -z.
+pub fn perspective(fovy: f32, aspect: f32, near: f32, far: f32) -> Matrix4<f32>
+{
+    let f = (fovy / 2.0).tan().recip();
+    Matrix4::new(
+        f / aspect, 0.0, 0.0, 0.0,
+        0.0, f, 0.0, 0.0,
+        0.0, 0.0, (far + near) / (far - near), 2.0 * far * near / (far - near),
+        0.0, 0.0, -1.0, 0.0)
+}
+
+/// An orthographic projection of a box `height` units tall, centered on the -z axis.
+pub fn orthographic(height: f32, aspect: f32, near: f32, far: f32) -> Matrix4<f32>
+{
+    Matrix4::new(
+        2.0 / (height * aspect), 0.0, 0.0, 0.0,
+        0.0, 2.0 / height, 0.0, 0.0,
+        0.0, 0.0, 2.0 / (far - near), (far + near) / (far - near),
+        0.0, 0.0, 0.0, 1.0)
+}
+
+/// A view matrix for a camera at `eye` looking towards `target`.
+pub fn look_at(eye: &Vector3<f32>, target: &Vector3<f32>, up: &Vector3<f32>) -> Matrix4<f32>
+{
+    let f = (*target - *eye).normalize();
+    let s = cross(&f, up).normalize();
+    let u = cross(&s, &f);
+    Matrix4::new(
+        s.x, s.y, s.z, -dot(&s, eye),
+        u.x, u.y, u.z, -dot(&u, eye),
+        -f.x, -f.y, -f.z, dot(&f, eye),
+        0.0, 0.0, 0.0, 1.0)
+}
+
+/// Applies `m` to the point `p`.
+pub fn transform(m: &Matrix4<f32>, p: &Vector3<f32>) -> Vector4<f32>
+{
+    *m * Vector4::new(p.x, p.y, p.z, 1.0)
+}
+
+/// Divides a clip-space position by its w, for points and lines, which the rasterizer draws at
+/// their x and y as given. Depth is left as it is. `None` if the position is behind the viewer.
+pub fn perspective_divide(p: &Vector4<f32>) -> Option<Vector4<f32>>
+{
+    if p.w > 0.0 {
+        Some(Vector4::new(p.x / p.w, p.y / p.w, p.z, 1.0))
+    } else {
+        None
+    }
+}
+
+/// A camera that circles `target`. At zero yaw and pitch it sits on the +z side looking down -z;
+/// positive pitch raises it above the target.
+#[derive(Copy, Clone, Debug)]
+pub struct OrbitCamera
+{
+    pub target: Vector3<f32>,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32
+}
+
+impl OrbitCamera
+{
+    pub fn new(target: Vector3<f32>, distance: f32) -> OrbitCamera
+    {
+        OrbitCamera {
+            target: target,
+            distance: distance,
+            yaw: 0.0,
+            pitch: 0.0
+        }
+    }
+    /// Pitch is kept just short of straight up or down, where the view would flip.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) -> ()
+    {
+        let limit = 1.55;
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).max(-limit).min(limit);
+    }
+    pub fn zoom(&mut self, factor: f32) -> ()
+    {
+        self.distance *= factor;
+    }
+    pub fn eye(&self) -> Vector3<f32>
+    {
+        self.target + Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos()) * self.distance
+    }
+    pub fn view(&self) -> Matrix4<f32>
+    {
+        look_at(&self.eye(), &self.target, &Vector3::new(0.0, 1.0, 0.0))
+    }
+}
+
+/// A free-flying camera. At zero yaw and pitch it looks down -z; positive pitch looks up.
+#[derive(Copy, Clone, Debug)]
+pub struct FlyCamera
+{
+    pub position: Vector3<f32>,
+    pub yaw: f32,
+    pub pitch: f32
+}
+
+impl FlyCamera
+{
+    pub fn new(position: Vector3<f32>) -> FlyCamera
+    {
+        FlyCamera {
+            position: position,
+            yaw: 0.0,
+            pitch: 0.0
+        }
+    }
+    pub fn turn(&mut self, yaw: f32, pitch: f32) -> ()
+    {
+        let limit = 1.55;
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).max(-limit).min(limit);
+    }
+    pub fn forward(&self) -> Vector3<f32>
+    {
+        Vector3::new(
+            -self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos())
+    }
+    pub fn right(&self) -> Vector3<f32>
+    {
+        Vector3::new(self.yaw.cos(), 0.0, -self.yaw.sin())
+    }
+    /// Moves along the view direction.
+    pub fn advance(&mut self, distance: f32) -> ()
+    {
+        self.position = self.position + self.forward() * distance;
+    }
+    /// Moves sideways, positive to the right.
+    pub fn strafe(&mut self, distance: f32) -> ()
+    {
+        self.position = self.position + self.right() * distance;
+    }
+    pub fn view(&self) -> Matrix4<f32>
+    {
+        let target = self.position + self.forward();
+        look_at(&self.position, &target, &Vector3::new(0.0, 1.0, 0.0))
+    }
+}
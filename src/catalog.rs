@@ -0,0 +1,225 @@
+extern crate nalgebra;
+
+use starfield::{Star, temperature_to_color};
+use Pixel;
+use nalgebra::Vector3;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// An error from reading a star catalog.
+#[derive(Debug)]
+pub enum CatalogError
+{
+    Io(io::Error),
+    /// A bad row, with the (1-based) line it was found on.
+    Parse(usize, String),
+    /// The header lacks a column the loader needs.
+    MissingColumn(String)
+}
+
+impl fmt::Display for CatalogError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            &CatalogError::Io(ref err) => write!(f, "{}", err),
+            &CatalogError::Parse(line, ref message) => write!(f, "line {}: {}", line, message),
+            &CatalogError::MissingColumn(ref name) => write!(f, "catalog has no '{}' column", name)
+        }
+    }
+}
+
+impl Error for CatalogError {}
+
+impl From<io::Error> for CatalogError
+{
+    fn from(err: io::Error) -> CatalogError
+    {
+        CatalogError::Io(err)
+    }
+}
+
+/// Splits a line of CSV, allowing commas and doubled quotes inside quoted fields.
+fn split_csv(line: &str) -> Vec<String>
+{
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(::std::mem::replace(&mut field, String::new())),
+            _ => field.push(c)
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// The effective temperature in kelvin of a star with the given B-V color index.
+pub fn bv_to_temperature(bv: f32) -> f32
+{
+    // Ballesteros' formula, which treats stars as black bodies.
+    let bv = bv.max(-0.4).min(2.0);
+    4600.0 * (1.0 / (0.92 * bv + 1.7) + 1.0 / (0.92 * bv + 0.62))
+}
+
+/// The color of a star with the given B-V color index.
+pub fn bv_to_color(bv: f32) -> Pixel
+{
+    temperature_to_color(bv_to_temperature(bv))
+}
+
+/// Direction on the unit sphere of the given equatorial coordinates, in radians. The celestial
+/// north pole is +y and right ascension 0 is +x, with right ascension increasing towards -z.
+pub fn equatorial_to_vector(ra: f32, dec: f32) -> Vector3<f32>
+{
+    let (ds, dc) = dec.sin_cos();
+    let (rs, rc) = ra.sin_cos();
+    Vector3::new(dc * rc, ds, -dc * rs)
+}
+
+/// Reads stars from a CSV catalog with a header row, such as the HYG database. Right ascension is
+/// read from `rarad` (radians) or `ra` (hours), declination from `decrad` (radians) or `dec`
+/// (degrees), magnitude from `mag` or `vmag`, and color index from `ci`, `bv` or `b-v`. Stars
+/// fainter than `limit`, or without a magnitude, are skipped; stars without a color index are
+/// given the sun's. A row at distance 0 (the sun itself, in HYG) is skipped too.
+///
+/// Stars are placed on the unit sphere as by `equatorial_to_vector`. To draw them, put them in a
+/// `Starfield` with `falloff` set to 0.
+pub fn parse_catalog<R: BufRead>(reader: R, limit: f32) -> Result<Vec<Star>, CatalogError>
+{
+    let mut lines = reader.lines();
+    let header = match lines.next() {
+        Some(line) => split_csv(&line?),
+        None => return Err(CatalogError::MissingColumn("ra".to_string()))
+    };
+    let column = |names: &[&str]| {
+        header.iter().position(|h| names.iter().any(|n| h.trim().eq_ignore_ascii_case(n)))
+    };
+    let (ra, ra_scale) = match (column(&["rarad"]), column(&["ra"])) {
+        (Some(i), _) => (i, 1.0),
+        (None, Some(i)) => (i, 15.0f32.to_radians()),
+        _ => return Err(CatalogError::MissingColumn("ra".to_string()))
+    };
+    let (dec, dec_scale) = match (column(&["decrad"]), column(&["dec"])) {
+        (Some(i), _) => (i, 1.0),
+        (None, Some(i)) => (i, 1.0f32.to_radians()),
+        _ => return Err(CatalogError::MissingColumn("dec".to_string()))
+    };
+    let mag = match column(&["mag", "vmag"]) {
+        Some(i) => i,
+        None => return Err(CatalogError::MissingColumn("mag".to_string()))
+    };
+    let bv = column(&["ci", "bv", "b-v"]);
+    let dist = column(&["dist"]);
+
+    let mut stars = Vec::new();
+    for (number, line) in lines.enumerate() {
+        let number = number + 2;
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_csv(&line);
+        let field = |i: usize| -> Result<Option<f32>, CatalogError> {
+            match fields.get(i).map(|f| f.trim()) {
+                None | Some("") => Ok(None),
+                Some(f) => match f.parse() {
+                    Ok(v) => Ok(Some(v)),
+                    Err(_) => Err(CatalogError::Parse(number, format!("expected a number, found '{}'", f)))
+                }
+            }
+        };
+        let magnitude = match field(mag)? {
+            Some(m) if m <= limit => m,
+            _ => continue
+        };
+        if let Some(d) = dist {
+            if field(d)? == Some(0.0) {
+                continue;
+            }
+        }
+        let (ra, dec) = match (field(ra)?, field(dec)?) {
+            (Some(ra), Some(dec)) => (ra * ra_scale, dec * dec_scale),
+            _ => return Err(CatalogError::Parse(number, "missing coordinates".to_string()))
+        };
+        let bv = match bv {
+            Some(i) => field(i)?.unwrap_or(0.65),
+            None => 0.65
+        };
+        stars.push(Star {
+            position: equatorial_to_vector(ra, dec),
+            magnitude: magnitude,
+            temperature: bv_to_temperature(bv),
+            // Spread the twinkle of neighbouring stars without needing a random source.
+            phase: (stars.len() as f32 * 2.399963) % (2.0 * ::std::f32::consts::PI)
+        });
+    }
+    Ok(stars)
+}
+
+pub fn load_catalog<P: AsRef<Path>>(path: P, limit: f32) -> Result<Vec<Star>, CatalogError>
+{
+    parse_catalog(BufReader::new(File::open(path)?), limit)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use nalgebra::Norm;
+
+    const HYG: &'static str = "id,proper,ra,dec,dist,mag,ci\n\
+        0,Sol,0,0,0,-26.7,0.656\n\
+        32263,Sirius,6.752481,-16.716116,2.6371,-1.44,0.009\n\
+        11734,Polaris,2.529744,89.264109,132.6260,1.97,\n\
+        1,,0.000060,1.089009,219.7802,9.1,0.482\n";
+
+    #[test]
+    fn magnitude_cutoff_and_the_sun_are_skipped()
+    {
+        let stars = parse_catalog(HYG.as_bytes(), 6.5).unwrap();
+        assert_eq!(stars.len(), 2);
+        assert_eq!(stars[0].magnitude, -1.44);
+        assert_eq!(parse_catalog(HYG.as_bytes(), 10.0).unwrap().len(), 3);
+        assert_eq!(parse_catalog(HYG.as_bytes(), -2.0).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn missing_color_index_gets_the_suns()
+    {
+        let stars = parse_catalog(HYG.as_bytes(), 6.5).unwrap();
+        assert_eq!(stars[1].temperature, bv_to_temperature(0.65));
+        assert!(stars[0].temperature > 9000.0);
+    }
+
+    #[test]
+    fn right_ascension_is_read_in_hours()
+    {
+        let stars = parse_catalog(HYG.as_bytes(), 6.5).unwrap();
+        let expected = equatorial_to_vector(6.752481 * 15.0f32.to_radians(), -16.716116f32.to_radians());
+        assert!((stars[0].position - expected).norm() < 1e-5);
+    }
+
+    #[test]
+    fn bad_rows_and_headers_are_errors()
+    {
+        match parse_catalog("ra,dec,mag\n1,2,3\n4,x,5\n".as_bytes(), 10.0) {
+            Err(CatalogError::Parse(3, _)) => (),
+            _ => panic!("expected a parse error on line 3")
+        }
+        match parse_catalog("ra,mag\n1,2\n".as_bytes(), 10.0) {
+            Err(CatalogError::MissingColumn(ref c)) if c == "dec" => (),
+            _ => panic!("expected a missing dec column")
+        }
+    }
+}
@@ -0,0 +1,342 @@
+extern crate rand;
+
+use buffer::{Buffer, DepthBuffer};
+use Pixel;
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::sync::OnceLock;
+
+/// How to spread the error when reducing pixels to a few colors or characters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Dither
+{
+    /// Round every pixel to its nearest color.
+    None,
+    /// Ordered dithering with a 4x4 or 8x8 Bayer matrix: regular cross-hatch patterns.
+    Bayer4,
+    Bayer8,
+    /// Ordered dithering with a blue noise tile: irregular, but without clumps.
+    BlueNoise,
+    /// Error diffusion passing all of each pixel's error on to its neighbours.
+    FloydSteinberg,
+    /// Error diffusion passing on only three quarters of the error, for more contrast.
+    Atkinson
+}
+
+/// A set of colors to reduce pixels to.
+pub struct Palette
+{
+    pub colors: Vec<Pixel>,
+    /// Roughly the distance between neighbouring colors in each channel. Ordered dithering nudges
+    /// pixels by up to this much.
+    pub spread: f32
+}
+
+impl Palette
+{
+    /// `levels` evenly spaced grays from black to white.
+    pub fn grayscale(levels: usize) -> Palette
+    {
+        let levels = levels.max(2);
+        Palette {
+            colors: (0..levels).map(|i| Pixel::Grayscale(i as f32 / (levels - 1) as f32)).collect(),
+            spread: 1.0 / (levels - 1) as f32
+        }
+    }
+    /// The 240 colors of the 256 color terminal palette above the first 16, which vary between
+    /// terminals: the 6x6x6 color cube then the gray ramp. Entry `i` is terminal color `i + 16`.
+    pub fn xterm256() -> Palette
+    {
+        let level = |i: usize| if i == 0 { 0.0 } else { (55.0 + 40.0 * i as f32) / 255.0 };
+        let mut colors = Vec::with_capacity(240);
+        for r in 0..6 {
+            for g in 0..6 {
+                for b in 0..6 {
+                    colors.push(Pixel::Color(level(r), level(g), level(b)));
+                }
+            }
+        }
+        for i in 0..24 {
+            colors.push(Pixel::Grayscale((8.0 + 10.0 * i as f32) / 255.0));
+        }
+        Palette {
+            colors: colors,
+            spread: 40.0 / 255.0
+        }
+    }
+    /// Index of the color closest to `(r, g, b)`.
+    pub fn nearest(&self, (r, g, b): (f32, f32, f32)) -> usize
+    {
+        let mut best = (0, ::std::f32::INFINITY);
+        for (i, color) in self.colors.iter().enumerate() {
+            let (cr, cg, cb) = color.rgb();
+            let distance = (r - cr) * (r - cr) + (g - cg) * (g - cg) + (b - cb) * (b - cb);
+            if distance < best.1 {
+                best = (i, distance);
+            }
+        }
+        best.0
+    }
+}
+
+/// Threshold from 0 to 1 of the `size` x `size` Bayer matrix at (x, y). `size` must be a power of
+/// two.
+pub fn bayer(size: usize, x: usize, y: usize) -> f32
+{
+    let bits = size.trailing_zeros();
+    let mut v = 0;
+    for i in 0..bits {
+        let (xb, yb) = ((x >> i) & 1, (y >> i) & 1);
+        v |= ((xb ^ yb) << 1 | yb) << (2 * (bits - 1 - i));
+    }
+    (v as f32 + 0.5) / (size * size) as f32
+}
+
+const NOISE_SIZE: usize = 32;
+
+/// A tileable `NOISE_SIZE` square of blue noise thresholds, made by the void-and-cluster method.
+fn blue_noise() -> Vec<f32>
+{
+    let n = NOISE_SIZE * NOISE_SIZE;
+    let sigma2 = 2.0 * 1.5f32 * 1.5;
+    // Energy contributed by a point at each offset, wrapping around the tile.
+    let mut kernel = vec![0.0; n];
+    for dy in 0..NOISE_SIZE {
+        for dx in 0..NOISE_SIZE {
+            let wx = dx.min(NOISE_SIZE - dx) as f32;
+            let wy = dy.min(NOISE_SIZE - dy) as f32;
+            kernel[dy * NOISE_SIZE + dx] = (-(wx * wx + wy * wy) / sigma2).exp();
+        }
+    }
+    let spread = |energy: &mut Vec<f32>, p: usize, sign: f32| {
+        let (px, py) = (p % NOISE_SIZE, p / NOISE_SIZE);
+        for y in 0..NOISE_SIZE {
+            for x in 0..NOISE_SIZE {
+                let k = ((y + NOISE_SIZE - py) % NOISE_SIZE) * NOISE_SIZE + (x + NOISE_SIZE - px) % NOISE_SIZE;
+                energy[y * NOISE_SIZE + x] += sign * kernel[k];
+            }
+        }
+    };
+    // The tightest cluster is the set point with the most energy, the largest void the unset
+    // point with the least.
+    let extreme = |energy: &Vec<f32>, set: &Vec<bool>, want: bool| {
+        let mut best: Option<(usize, f32)> = None;
+        for i in 0..n {
+            if set[i] == want {
+                let better = match best {
+                    None => true,
+                    Some((_, e)) => if want { energy[i] > e } else { energy[i] < e }
+                };
+                if better {
+                    best = Some((i, energy[i]));
+                }
+            }
+        }
+        best.map(|(i, _)| i).unwrap_or(0)
+    };
+
+    // Start from a random pattern and move points out of clusters into voids until it settles.
+    let mut rng = XorShiftRng::from_seed([0x2545f491, 0x4f6cdd1d, 0x9e3779b9, 0x7f4a7c15]);
+    let mut set = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let initial = n / 10;
+    let mut placed = 0;
+    while placed < initial {
+        let p = rng.gen_range(0, n);
+        if !set[p] {
+            set[p] = true;
+            spread(&mut energy, p, 1.0);
+            placed += 1;
+        }
+    }
+    for _ in 0..n {
+        let cluster = extreme(&energy, &set, true);
+        set[cluster] = false;
+        spread(&mut energy, cluster, -1.0);
+        let void = extreme(&energy, &set, false);
+        set[void] = true;
+        spread(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; n];
+    // Rank the starting points by taking clusters away, then fill the rest in void by void.
+    let (start_set, start_energy) = (set.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = extreme(&energy, &set, true);
+        set[cluster] = false;
+        spread(&mut energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+    set = start_set;
+    energy = start_energy;
+    for r in initial..n {
+        let void = extreme(&energy, &set, false);
+        set[void] = true;
+        spread(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+    rank.iter().map(|&r| (r as f32 + 0.5) / n as f32).collect()
+}
+
+/// The blue noise tile, made the first time any `Ditherer` needs it and shared after that, so
+/// `method` can be changed freely.
+fn blue_noise_tile() -> &'static [f32]
+{
+    static NOISE: OnceLock<Vec<f32>> = OnceLock::new();
+    NOISE.get_or_init(blue_noise)
+}
+
+/// Reduces rendered pixels to a palette or to characters with a chosen dithering method.
+pub struct Ditherer
+{
+    pub method: Dither,
+    /// Change the pattern every frame, so that in animations the eye averages it out instead of
+    /// seeing it crawl over still parts of the picture. Advance with `next_frame`.
+    pub temporal: bool,
+    pub frame: u32
+}
+
+impl Ditherer
+{
+    pub fn new(method: Dither) -> Ditherer
+    {
+        Ditherer {
+            method: method,
+            temporal: false,
+            frame: 0
+        }
+    }
+    pub fn next_frame(&mut self) -> ()
+    {
+        self.frame = self.frame.wrapping_add(1);
+    }
+    /// The ordered dithering threshold at (x, y), from 0 to 1, or 0.5 for methods that don't use
+    /// one.
+    pub fn threshold(&self, x: usize, y: usize) -> f32
+    {
+        let t = match self.method {
+            Dither::Bayer4 => bayer(4, x % 4, y % 4),
+            Dither::Bayer8 => bayer(8, x % 8, y % 8),
+            Dither::BlueNoise => blue_noise_tile()[(y % NOISE_SIZE) * NOISE_SIZE + x % NOISE_SIZE],
+            _ => return 0.5
+        };
+        if self.temporal {
+            // Stepping by the golden ratio visits every threshold evenly over time.
+            (t + self.frame as f32 * 0.618034) % 1.0
+        } else {
+            t
+        }
+    }
+    /// The index into `palette` chosen for each pixel, or `None` where nothing was drawn.
+    pub fn quantize(&self, buf: &DepthBuffer<Pixel>, palette: &Palette) -> Buffer<Option<usize>>
+    {
+        let mut out = Buffer::new(buf.width, buf.height, None);
+        match self.method {
+            Dither::FloydSteinberg => self.diffuse(buf, palette, &mut out, &[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0),
+            Dither::Atkinson => self.diffuse(buf, palette, &mut out, &[(1, 0, 1.0), (2, 0, 1.0), (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)], 8.0),
+            _ => {
+                for y in 0..buf.height {
+                    for x in 0..buf.width {
+                        if let &Some((ref p, _)) = buf.get(x, y) {
+                            let offset = if self.method == Dither::None { 0.0 } else { (self.threshold(x, y) - 0.5) * palette.spread };
+                            let (r, g, b) = p.rgb();
+                            out.set(x, y, Some(palette.nearest((r + offset, g + offset, b + offset))));
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+    /// Error diffusion in screen order, from the top row down. `kernel` lists (dx, dy down,
+    /// weight) and the weights are divided by `total`.
+    fn diffuse(&self, buf: &DepthBuffer<Pixel>, palette: &Palette, out: &mut Buffer<Option<usize>>, kernel: &[(i32, i32, f32)], total: f32) -> ()
+    {
+        let (width, height) = (buf.width as i32, buf.height as i32);
+        let mut error = Buffer::new(buf.width, buf.height, (0.0f32, 0.0f32, 0.0f32));
+        // Alternate the direction of each row; temporal dithering also flips it every frame.
+        let flip = self.temporal && self.frame % 2 == 1;
+        for row in 0..height {
+            let y = height - 1 - row;
+            let backwards = (row % 2 == 1) != flip;
+            for column in 0..width {
+                let x = if backwards { width - 1 - column } else { column };
+                let p = match buf.get(x as usize, y as usize) {
+                    &Some((ref p, _)) => p.rgb(),
+                    &None => continue
+                };
+                let e = *error.get(x as usize, y as usize);
+                let wanted = (p.0 + e.0, p.1 + e.1, p.2 + e.2);
+                let index = palette.nearest(wanted);
+                out.set(x as usize, y as usize, Some(index));
+                let (cr, cg, cb) = palette.colors[index].rgb();
+                let diff = (wanted.0 - cr, wanted.1 - cg, wanted.2 - cb);
+                for &(dx, dy, weight) in kernel {
+                    let (nx, ny) = (if backwards { x - dx } else { x + dx }, y - dy);
+                    if nx < 0 || nx >= width || ny < 0 {
+                        continue;
+                    }
+                    let w = weight / total;
+                    let slot = error.get(nx as usize, ny as usize).clone();
+                    error.set(nx as usize, ny as usize, (slot.0 + diff.0 * w, slot.1 + diff.1 * w, slot.2 + diff.2 * w));
+                }
+            }
+        }
+    }
+    /// 256 color terminal codes for each pixel, with 0 (black) where nothing was drawn.
+    pub fn quantize_256(&self, buf: &DepthBuffer<Pixel>) -> Buffer<u8>
+    {
+        let palette = Palette::xterm256();
+        let indices = self.quantize(buf, &palette);
+        let mut out = Buffer::new(buf.width, buf.height, 0);
+        for y in 0..buf.height {
+            for x in 0..buf.width {
+                if let &Some(i) = indices.get(x, y) {
+                    out.set(x, y, (i + 16) as u8);
+                }
+            }
+        }
+        out
+    }
+    /// A character for each pixel picked by brightness from `ramp`, which runs from darkest to
+    /// brightest and must not be empty. Pixels where nothing was drawn get a space.
+    pub fn quantize_chars(&self, buf: &DepthBuffer<Pixel>, ramp: &[char]) -> Buffer<char>
+    {
+        let mut gray = Buffer::new(buf.width, buf.height, None);
+        for y in 0..buf.height {
+            for x in 0..buf.width {
+                if let &Some((ref p, depth)) = buf.get(x, y) {
+                    let (r, g, b) = p.rgb();
+                    gray.set(x, y, Some((Pixel::Grayscale(0.2126 * r + 0.7152 * g + 0.0722 * b), depth)));
+                }
+            }
+        }
+        let indices = self.quantize(&gray, &Palette::grayscale(ramp.len()));
+        let mut out = Buffer::new(buf.width, buf.height, ' ');
+        for y in 0..buf.height {
+            for x in 0..buf.width {
+                if let &Some(i) = indices.get(x, y) {
+                    out.set(x, y, ramp[i.min(ramp.len() - 1)]);
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn method_can_be_switched_to_blue_noise()
+    {
+        let mut ditherer = Ditherer::new(Dither::Bayer4);
+        ditherer.method = Dither::BlueNoise;
+        let t = ditherer.threshold(40, 7);
+        assert!(t > 0.0 && t < 1.0);
+    }
+}
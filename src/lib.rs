@@ -1,12 +1,44 @@
 extern crate nalgebra;
+extern crate rand;
 
+#[macro_use]
+mod varying;
 mod buffer;
+mod camera;
+mod catalog;
+mod dither;
+mod lighting;
+mod mesh;
+mod obj;
+mod ply;
+mod primitives;
 mod render;
+mod shadow;
+mod sky;
+mod starfield;
+mod stl;
 mod text;
+mod texture;
+mod warp;
 
 pub use buffer::*;
+pub use camera::*;
+pub use catalog::*;
+pub use dither::*;
+pub use lighting::*;
+pub use mesh::*;
+pub use obj::*;
+pub use ply::*;
+pub use primitives::*;
 pub use render::*;
+pub use shadow::*;
+pub use sky::*;
+pub use starfield::*;
+pub use stl::*;
 pub use text::*;
+pub use texture::*;
+pub use varying::*;
+pub use warp::*;
 use std::ops::{Add,Mul};
 use nalgebra::{Vector4, Vector3, Vector2, Norm, dot, cross};
 
@@ -17,6 +49,62 @@ pub enum Pixel
     Grayscale(f32)
 }
 
+impl Pixel
+{
+    pub fn rgb(&self) -> (f32, f32, f32)
+    {
+        match self {
+            &Pixel::Color(r, g, b) => (r, g, b),
+            &Pixel::Grayscale(v) => (v, v, v)
+        }
+    }
+}
+
+// Pixels blend channel by channel, so they can be interpolated and filtered. Mixing in a color
+// makes the result a color.
+impl Add for Pixel
+{
+    type Output = Pixel;
+    fn add(self, other: Pixel) -> Pixel
+    {
+        match (self, other) {
+            (Pixel::Grayscale(a), Pixel::Grayscale(b)) => Pixel::Grayscale(a + b),
+            (a, b) => {
+                let ((ar, ag, ab), (br, bg, bb)) = (a.rgb(), b.rgb());
+                Pixel::Color(ar + br, ag + bg, ab + bb)
+            }
+        }
+    }
+}
+
+impl Mul<f32> for Pixel
+{
+    type Output = Pixel;
+    fn mul(self, v: f32) -> Pixel
+    {
+        match self {
+            Pixel::Color(r, g, b) => Pixel::Color(r*v, g*v, b*v),
+            Pixel::Grayscale(g) => Pixel::Grayscale(g*v)
+        }
+    }
+}
+
+// Multiplying two pixels tints one by the other, as when lighting a surface.
+impl Mul for Pixel
+{
+    type Output = Pixel;
+    fn mul(self, other: Pixel) -> Pixel
+    {
+        match (self, other) {
+            (Pixel::Grayscale(a), Pixel::Grayscale(b)) => Pixel::Grayscale(a * b),
+            (a, b) => {
+                let ((ar, ag, ab), (br, bg, bb)) = (a.rgb(), b.rgb());
+                Pixel::Color(ar * br, ag * bg, ab * bb)
+            }
+        }
+    }
+}
+
 // I always relish the opportunity to place what looks like indecipherable alien symbology in my
 // code.
 static blocks: [char; 16] = [' ','▘','▝','▀','▖','▌','▞','▛','▗','▚','▐','▜','▄','▙','▟','█'];
@@ -65,7 +153,18 @@ pub fn to_256_color(p: &Pixel, x: usize, y: usize) -> u8
                 res as u8
             }
         },
-        _ => panic!("unimplimented")
+        &Pixel::Color(r, g, b) => {
+            // Each channel picks one of the six levels of the color cube, dithered like grayscale.
+            let level = |c: f32| {
+                let val = (c * 5.0).max(0.0).min(5.0);
+                if dither_2(((val - val.floor()) * 4.0) as usize, x, y) {
+                    (val as usize + 1).min(5)
+                } else {
+                    val as usize
+                }
+            };
+            (16 + 36*level(r) + 6*level(g) + level(b)) as u8
+        }
     }
 }
 
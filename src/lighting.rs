@@ -0,0 +1,218 @@
+extern crate nalgebra;
+
+use obj::Material;
+use Pixel;
+use nalgebra::{Vector3, Norm, dot};
+use std::f32::consts::PI;
+use std::ops::{Add, Mul};
+
+/// World-space position and normal of a point on a surface, to be passed from the vertex shader to
+/// the fragment shader. Interpolated normals aren't unit length; the shading functions normalize
+/// them.
+#[derive(Copy, Clone, Debug)]
+pub struct Surface
+{
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>
+}
+
+impl Add for Surface
+{
+    type Output = Surface;
+    fn add(self, other: Surface) -> Surface
+    {
+        Surface {
+            position: self.position + other.position,
+            normal: self.normal + other.normal
+        }
+    }
+}
+
+impl Mul<f32> for Surface
+{
+    type Output = Surface;
+    fn mul(self, v: f32) -> Surface
+    {
+        Surface {
+            position: self.position * v,
+            normal: self.normal * v
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Light
+{
+    /// Light from infinitely far away, such as the sun, travelling along `direction`.
+    Directional
+    {
+        direction: Vector3<f32>,
+        color: Pixel
+    },
+    /// Light shining out in all directions from `position`, falling off with the square of
+    /// distance.
+    Point
+    {
+        position: Vector3<f32>,
+        color: Pixel
+    },
+    /// A point light limited to a cone around `direction`. It is at full strength within `inner`
+    /// radians of the axis and fades out by `outer`.
+    Spot
+    {
+        position: Vector3<f32>,
+        direction: Vector3<f32>,
+        color: Pixel,
+        inner: f32,
+        outer: f32
+    }
+}
+
+impl Light
+{
+    /// The unit direction from `point` towards the light and the light arriving there, or `None`
+    /// if no light arrives.
+    pub fn incident(&self, point: &Vector3<f32>) -> Option<(Vector3<f32>, Pixel)>
+    {
+        match self {
+            &Light::Directional { direction, color } => Some((-direction.normalize(), color)),
+            &Light::Point { position, color } => {
+                let offset = position - *point;
+                let distance_squared = offset.norm_squared();
+                if distance_squared > 0.0 {
+                    Some((offset / distance_squared.sqrt(), color * (1.0 / distance_squared)))
+                } else {
+                    None
+                }
+            },
+            &Light::Spot { position, direction, color, inner, outer } => {
+                let offset = position - *point;
+                let distance_squared = offset.norm_squared();
+                if distance_squared == 0.0 {
+                    return None;
+                }
+                let to_light = offset / distance_squared.sqrt();
+                let angle = dot(&-to_light, &direction.normalize()).max(-1.0).min(1.0).acos();
+                let cone = if outer > inner {
+                    ((outer - angle) / (outer - inner)).max(0.0).min(1.0)
+                } else if angle <= outer {
+                    1.0
+                } else {
+                    0.0
+                };
+                if cone > 0.0 {
+                    Some((to_light, color * (cone / distance_squared)))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Lambert's diffuse term: how squarely the surface faces the light.
+pub fn lambert(normal: &Vector3<f32>, to_light: &Vector3<f32>) -> f32
+{
+    dot(normal, to_light).max(0.0)
+}
+
+/// The Blinn-Phong specular term, with highlights getting tighter as `shininess` grows.
+pub fn blinn_phong(normal: &Vector3<f32>, to_light: &Vector3<f32>, to_eye: &Vector3<f32>, shininess: f32) -> f32
+{
+    if dot(normal, to_light) <= 0.0 {
+        return 0.0;
+    }
+    let half = *to_light + *to_eye;
+    if half.norm_squared() == 0.0 {
+        return 0.0;
+    }
+    dot(normal, &half.normalize()).max(0.0).powf(shininess)
+}
+
+/// Parameters of the metallic-roughness model used by `shade_pbr`.
+#[derive(Copy, Clone, Debug)]
+pub struct PbrMaterial
+{
+    /// Diffuse color of a dielectric, or reflected color of a metal.
+    pub albedo: Pixel,
+    /// From 0 (plastic, stone) to 1 (bare metal).
+    pub metallic: f32,
+    /// From 0 (mirror smooth) to 1 (completely rough).
+    pub roughness: f32
+}
+
+/// Cook-Torrance reflectance with a GGX distribution, Smith-Schlick geometry and Schlick's
+/// Fresnel, plus a Lambertian diffuse lobe. Returns the fraction of incoming light reflected
+/// towards the eye, per channel, already multiplied by the cosine of the light's angle.
+pub fn pbr(normal: &Vector3<f32>, to_light: &Vector3<f32>, to_eye: &Vector3<f32>, material: &PbrMaterial) -> Pixel
+{
+    let n_l = dot(normal, to_light);
+    let n_v = dot(normal, to_eye).max(1e-4);
+    if n_l <= 0.0 {
+        return Pixel::Grayscale(0.0);
+    }
+    let half = *to_light + *to_eye;
+    if half.norm_squared() == 0.0 {
+        return Pixel::Grayscale(0.0);
+    }
+    let half = half.normalize();
+    let n_h = dot(normal, &half).max(0.0);
+    let v_h = dot(to_eye, &half).max(0.0);
+
+    let alpha = (material.roughness * material.roughness).max(1e-3);
+    let alpha2 = alpha * alpha;
+    let d = alpha2 / (PI * (n_h * n_h * (alpha2 - 1.0) + 1.0).powi(2));
+    let k = (material.roughness + 1.0).powi(2) / 8.0;
+    let g = (n_l / (n_l * (1.0 - k) + k)) * (n_v / (n_v * (1.0 - k) + k));
+
+    // Dielectrics reflect about 4% head-on; metals reflect their albedo.
+    let f0 = Pixel::Grayscale(0.04) * (1.0 - material.metallic) + material.albedo * material.metallic;
+    let fresnel = f0 + (Pixel::Grayscale(1.0) + f0 * -1.0) * (1.0 - v_h).powi(5);
+    let specular = fresnel * (d * g / (4.0 * n_l * n_v));
+    let diffuse = (Pixel::Grayscale(1.0) + fresnel * -1.0) * material.albedo * ((1.0 - material.metallic) / PI);
+    (diffuse + specular) * (n_l * PI)
+}
+
+/// Diffuse lighting of a surface of color `albedo`, plus `ambient` light from everywhere.
+pub fn shade_lambert(surface: &Surface, lights: &[Light], albedo: Pixel, ambient: Pixel) -> Pixel
+{
+    let normal = surface.normal.normalize();
+    let mut total = ambient;
+    for light in lights {
+        if let Some((to_light, color)) = light.incident(&surface.position) {
+            total = total + color * lambert(&normal, &to_light);
+        }
+    }
+    total * albedo
+}
+
+/// Blinn-Phong lighting with the colors and shininess of an OBJ `Material`, seen from `eye`.
+pub fn shade_blinn_phong(surface: &Surface, eye: &Vector3<f32>, lights: &[Light], material: &Material) -> Pixel
+{
+    let normal = surface.normal.normalize();
+    let to_eye = (*eye - surface.position).normalize();
+    let mut total = material.ambient;
+    for light in lights {
+        if let Some((to_light, color)) = light.incident(&surface.position) {
+            total = total + color * material.diffuse * lambert(&normal, &to_light);
+            total = total + color * material.specular * blinn_phong(&normal, &to_light, &to_eye, material.shininess);
+        }
+    }
+    total
+}
+
+/// Physically based lighting seen from `eye`, with `ambient` light tinted by the albedo.
+pub fn shade_pbr(surface: &Surface, eye: &Vector3<f32>, lights: &[Light], material: &PbrMaterial, ambient: Pixel) -> Pixel
+{
+    let normal = surface.normal.normalize();
+    let to_eye = (*eye - surface.position).normalize();
+    let mut total = ambient * material.albedo;
+    for light in lights {
+        if let Some((to_light, color)) = light.incident(&surface.position) {
+            total = total + color * pbr(&normal, &to_light, &to_eye, material);
+        }
+    }
+    total
+}
+
+impl_varying_ops!(Surface);
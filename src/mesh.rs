@@ -0,0 +1,235 @@
+extern crate nalgebra;
+
+use buffer::DepthBuffer;
+use render::{Patch, Fragment, Varying, process};
+use Pixel;
+use nalgebra::{Vector2, Vector3, Vector4, Norm, cross};
+use std::ops::{Add, Mul};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// An error from one of the model loaders.
+#[derive(Debug)]
+pub enum MeshError
+{
+    Io(io::Error),
+    /// Malformed text input, with the (1-based) line it was found on.
+    Parse(usize, String),
+    /// Malformed binary input, or a feature the loader doesn't handle.
+    Format(String)
+}
+
+impl fmt::Display for MeshError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            &MeshError::Io(ref err) => write!(f, "{}", err),
+            &MeshError::Parse(line, ref message) => write!(f, "line {}: {}", line, message),
+            &MeshError::Format(ref message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+impl From<io::Error> for MeshError
+{
+    fn from(err: io::Error) -> MeshError
+    {
+        MeshError::Io(err)
+    }
+}
+
+/// Indexed geometry, as produced by the model loaders. `normals`, `uvs` and `colors` are either
+/// empty or hold one entry per position.
+pub struct Mesh
+{
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub uvs: Vec<Vector2<f32>>,
+    pub colors: Vec<Pixel>,
+    pub patches: Vec<Patch>
+}
+
+impl Mesh
+{
+    pub fn new() -> Mesh
+    {
+        Mesh {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            colors: Vec::new(),
+            patches: Vec::new()
+        }
+    }
+    /// The smallest and largest corners of the box around all positions, or `None` if there are
+    /// no positions.
+    pub fn bounds(&self) -> Option<(Vector3<f32>, Vector3<f32>)>
+    {
+        if self.positions.is_empty() {
+            return None;
+        }
+        let mut min = self.positions[0];
+        let mut max = self.positions[0];
+        for p in self.positions.iter() {
+            min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        Some((min, max))
+    }
+    /// Moves the mesh so its bounding box is centered on the origin.
+    pub fn recenter(&mut self) -> ()
+    {
+        if let Some((min, max)) = self.bounds() {
+            let center = (min + max) * 0.5;
+            for p in self.positions.iter_mut() {
+                *p = *p - center;
+            }
+        }
+    }
+    /// Recenters the mesh and scales it uniformly so that it fits in the cube from -1 to 1.
+    pub fn normalize(&mut self) -> ()
+    {
+        self.recenter();
+        if let Some((min, max)) = self.bounds() {
+            let size = max - min;
+            let largest = size.x.max(size.y).max(size.z);
+            if largest > 0.0 {
+                let scale = 2.0 / largest;
+                for p in self.positions.iter_mut() {
+                    *p = *p * scale;
+                }
+            }
+        }
+    }
+    /// Replaces the normals with ones averaged from the surrounding triangles, weighted by their
+    /// area.
+    pub fn smooth_normals(&mut self) -> ()
+    {
+        let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); self.positions.len()];
+        for patch in self.patches.iter() {
+            if let &Patch::Tri(a, b, c) = patch {
+                let n = face_normal(&self.positions[a], &self.positions[b], &self.positions[c]);
+                normals[a] = normals[a] + n;
+                normals[b] = normals[b] + n;
+                normals[c] = normals[c] + n;
+            }
+        }
+        for n in normals.iter_mut() {
+            if n.norm_squared() > 0.0 {
+                *n = n.normalize();
+            }
+        }
+        self.normals = normals;
+    }
+    /// Gives every triangle its own copies of its vertices and sets their normals to the
+    /// triangle's, so it is shaded flat. Points and lines are copied along with it.
+    pub fn flat_normals(&mut self) -> ()
+    {
+        let mut mesh = Mesh::new();
+        for patch in self.patches.iter() {
+            let start = mesh.positions.len();
+            let corners = match patch {
+                &Patch::Point(a) => vec![a],
+                &Patch::Line(a, b) => vec![a, b],
+                &Patch::Tri(a, b, c) => vec![a, b, c]
+            };
+            let normal = match patch {
+                &Patch::Tri(a, b, c) => {
+                    let n = face_normal(&self.positions[a], &self.positions[b], &self.positions[c]);
+                    if n.norm_squared() > 0.0 { Some(n.normalize()) } else { Some(n) }
+                },
+                _ => None
+            };
+            for &i in corners.iter() {
+                mesh.positions.push(self.positions[i]);
+                if let Some(n) = normal {
+                    mesh.normals.push(n);
+                } else if !self.normals.is_empty() {
+                    mesh.normals.push(self.normals[i]);
+                } else {
+                    mesh.normals.push(Vector3::new(0.0, 0.0, 0.0));
+                }
+                if !self.uvs.is_empty() {
+                    mesh.uvs.push(self.uvs[i]);
+                }
+                if !self.colors.is_empty() {
+                    mesh.colors.push(self.colors[i]);
+                }
+            }
+            mesh.patches.push(match patch {
+                &Patch::Point(_) => Patch::Point(start),
+                &Patch::Line(_, _) => Patch::Line(start, start+1),
+                &Patch::Tri(_, _, _) => Patch::Tri(start, start+1, start+2)
+            });
+        }
+        *self = mesh;
+    }
+    /// Gathers the attribute streams into one value per vertex. Missing normals and UVs are zero
+    /// and missing colors are white.
+    pub fn vertices(&self) -> Vec<MeshVertex>
+    {
+        (0..self.positions.len()).map(|i| {
+            MeshVertex {
+                position: self.positions[i],
+                normal: self.normals.get(i).cloned().unwrap_or(Vector3::new(0.0, 0.0, 0.0)),
+                uv: self.uvs.get(i).cloned().unwrap_or(Vector2::new(0.0, 0.0)),
+                color: self.colors.get(i).cloned().unwrap_or(Pixel::Grayscale(1.0))
+            }
+        }).collect()
+    }
+    /// Runs the mesh through `process`, with the vertex closure receiving each `MeshVertex`.
+    pub fn draw<I,U,T,E,F>(&self, buf: &mut DepthBuffer<T>, uniform: &U, vertex: E, fragment: F) -> ()
+        where I: Varying, E: Fn(&U, &MeshVertex) -> (Vector4<f32>, I), F: Fragment<U, I, T>
+    {
+        process(buf, uniform, &self.vertices(), &self.patches, vertex, fragment)
+    }
+}
+
+fn face_normal(a: &Vector3<f32>, b: &Vector3<f32>, c: &Vector3<f32>) -> Vector3<f32>
+{
+    cross(&(*b - *a), &(*c - *a))
+}
+
+/// All of a mesh's attributes for one vertex. It can be passed straight through as a varying.
+#[derive(Copy, Clone, Debug)]
+pub struct MeshVertex
+{
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub uv: Vector2<f32>,
+    pub color: Pixel
+}
+
+impl Add for MeshVertex
+{
+    type Output = MeshVertex;
+    fn add(self, other: MeshVertex) -> MeshVertex
+    {
+        MeshVertex {
+            position: self.position + other.position,
+            normal: self.normal + other.normal,
+            uv: self.uv + other.uv,
+            color: self.color + other.color
+        }
+    }
+}
+
+impl Mul<f32> for MeshVertex
+{
+    type Output = MeshVertex;
+    fn mul(self, v: f32) -> MeshVertex
+    {
+        MeshVertex {
+            position: self.position * v,
+            normal: self.normal * v,
+            uv: self.uv * v,
+            color: self.color * v
+        }
+    }
+}
+
+impl_varying_ops!(MeshVertex);
@@ -0,0 +1,353 @@
+extern crate nalgebra;
+
+use mesh::{Mesh, MeshError};
+use render::Patch;
+use Pixel;
+use nalgebra::{Vector2, Vector3};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::SplitWhitespace;
+
+#[derive(Clone, Debug)]
+pub struct Material
+{
+    pub name: String,
+    pub ambient: Pixel,
+    pub diffuse: Pixel,
+    pub specular: Pixel,
+    pub shininess: f32,
+    pub opacity: f32,
+    /// File name of the diffuse texture, as written in the MTL file.
+    pub diffuse_map: Option<String>
+}
+
+impl Material
+{
+    fn new(name: &str) -> Material
+    {
+        Material {
+            name: name.to_string(),
+            ambient: Pixel::Color(0.0, 0.0, 0.0),
+            diffuse: Pixel::Color(0.8, 0.8, 0.8),
+            specular: Pixel::Color(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            opacity: 1.0,
+            diffuse_map: None
+        }
+    }
+}
+
+/// A parsed OBJ file. Faces are triangulated into `Patch::Tri`, polylines become `Patch::Line`
+/// and point elements `Patch::Point`.
+pub struct Obj
+{
+    pub mesh: Mesh,
+    /// MTL files named by `mtllib`.
+    pub material_libs: Vec<String>,
+    /// Materials named by `usemtl`, in order of first use.
+    pub material_names: Vec<String>,
+    /// For each patch, its index into `material_names`.
+    pub patch_materials: Vec<Option<usize>>,
+    /// Materials loaded from `material_libs`. Only filled in by `load_obj`.
+    pub materials: Vec<Material>
+}
+
+impl Obj
+{
+    /// The material used by patch `index`, if it has one and it was loaded.
+    pub fn material(&self, index: usize) -> Option<&Material>
+    {
+        self.patch_materials[index].and_then(|m| {
+            let name = &self.material_names[m];
+            self.materials.iter().find(|mat| &mat.name == name)
+        })
+    }
+}
+
+fn parse_floats(words: SplitWhitespace, line: usize, min: usize, max: usize) -> Result<Vec<f32>, MeshError>
+{
+    let mut out = Vec::new();
+    for word in words {
+        match word.parse() {
+            Ok(v) => out.push(v),
+            Err(_) => return Err(MeshError::Parse(line, format!("expected a number, found '{}'", word)))
+        }
+    }
+    if out.len() < min || out.len() > max {
+        return Err(MeshError::Parse(line, format!("expected {} to {} numbers, found {}", min, max, out.len())));
+    }
+    Ok(out)
+}
+
+// OBJ indices are 1-based, and negative ones count back from the most recent element.
+fn resolve_index(word: &str, count: usize, line: usize) -> Result<usize, MeshError>
+{
+    let index: i64 = match word.parse() {
+        Ok(i) => i,
+        Err(_) => return Err(MeshError::Parse(line, format!("bad index '{}'", word)))
+    };
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(MeshError::Parse(line, format!("index {} out of range", index)));
+    }
+    Ok(resolved as usize)
+}
+
+/// Parses OBJ data. Material libraries are not loaded; see `load_obj`.
+pub fn parse_obj<R: BufRead>(reader: R) -> Result<Obj, MeshError>
+{
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+
+    // Each distinct position/uv/normal combination becomes one vertex of the mesh.
+    let mut vertices: Vec<(usize, Option<usize>, Option<usize>)> = Vec::new();
+    let mut lookup = HashMap::new();
+
+    let mut obj = Obj {
+        mesh: Mesh::new(),
+        material_libs: Vec::new(),
+        material_names: Vec::new(),
+        patch_materials: Vec::new(),
+        materials: Vec::new()
+    };
+    let mut material = None;
+
+    for (number, line) in reader.lines().enumerate() {
+        let number = number + 1;
+        let line = line?;
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) => k,
+            None => continue
+        };
+        match keyword {
+            "v" => {
+                // Either x y z [w], or x y z r g b with vertex colors.
+                let v = parse_floats(words, number, 3, 6)?;
+                if v.len() == 5 {
+                    return Err(MeshError::Parse(number, "expected 3, 4 or 6 numbers, found 5".to_string()));
+                }
+                let w = if v.len() == 4 { v[3] } else { 1.0 };
+                if w == 0.0 {
+                    return Err(MeshError::Parse(number, "vertex has a w of zero".to_string()));
+                }
+                positions.push(Vector3::new(v[0]/w, v[1]/w, v[2]/w));
+                colors.push(if v.len() == 6 { Some(Pixel::Color(v[3], v[4], v[5])) } else { None });
+            },
+            "vt" => {
+                let v = parse_floats(words, number, 1, 3)?;
+                uvs.push(Vector2::new(v[0], if v.len() > 1 { v[1] } else { 0.0 }));
+            },
+            "vn" => {
+                let v = parse_floats(words, number, 3, 3)?;
+                normals.push(Vector3::new(v[0], v[1], v[2]));
+            },
+            "f" | "l" | "p" => {
+                let mut indices = Vec::new();
+                for word in words {
+                    let mut parts = word.split('/');
+                    let v = resolve_index(parts.next().unwrap_or(""), positions.len(), number)?;
+                    let vt = match parts.next() {
+                        Some(p) if p.len() > 0 => Some(resolve_index(p, uvs.len(), number)?),
+                        _ => None
+                    };
+                    let vn = match parts.next() {
+                        Some(p) if p.len() > 0 => Some(resolve_index(p, normals.len(), number)?),
+                        _ => None
+                    };
+                    let key = (v, vt, vn);
+                    let index = *lookup.entry(key).or_insert_with(|| {
+                        vertices.push(key);
+                        vertices.len() - 1
+                    });
+                    indices.push(index);
+                }
+                let before = obj.mesh.patches.len();
+                match keyword {
+                    "f" => {
+                        if indices.len() < 3 {
+                            return Err(MeshError::Parse(number, "face with fewer than 3 vertices".to_string()));
+                        }
+                        for i in 1..(indices.len()-1) {
+                            obj.mesh.patches.push(Patch::Tri(indices[0], indices[i], indices[i+1]));
+                        }
+                    },
+                    "l" => {
+                        if indices.len() < 2 {
+                            return Err(MeshError::Parse(number, "line with fewer than 2 vertices".to_string()));
+                        }
+                        for i in 0..(indices.len()-1) {
+                            obj.mesh.patches.push(Patch::Line(indices[i], indices[i+1]));
+                        }
+                    },
+                    _ => {
+                        for &i in indices.iter() {
+                            obj.mesh.patches.push(Patch::Point(i));
+                        }
+                    }
+                }
+                for _ in before..obj.mesh.patches.len() {
+                    obj.patch_materials.push(material);
+                }
+            },
+            "mtllib" => {
+                obj.material_libs.extend(words.map(|w| w.to_string()));
+            },
+            "usemtl" => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                material = Some(match obj.material_names.iter().position(|n| n == &name) {
+                    Some(i) => i,
+                    None => {
+                        obj.material_names.push(name);
+                        obj.material_names.len() - 1
+                    }
+                });
+            },
+            // Groups, objects, smoothing groups and anything else don't affect the geometry.
+            _ => {}
+        }
+    }
+
+    let has_uvs = vertices.iter().any(|&(_, vt, _)| vt.is_some());
+    let has_normals = vertices.iter().any(|&(_, _, vn)| vn.is_some());
+    let has_colors = colors.iter().any(|c| c.is_some());
+    for &(v, vt, vn) in vertices.iter() {
+        obj.mesh.positions.push(positions[v]);
+        if has_colors {
+            obj.mesh.colors.push(colors[v].unwrap_or(Pixel::Color(1.0, 1.0, 1.0)));
+        }
+        if has_uvs {
+            obj.mesh.uvs.push(vt.map(|i| uvs[i]).unwrap_or(Vector2::new(0.0, 0.0)));
+        }
+        if has_normals {
+            obj.mesh.normals.push(vn.map(|i| normals[i]).unwrap_or(Vector3::new(0.0, 0.0, 0.0)));
+        }
+    }
+    Ok(obj)
+}
+
+/// Parses MTL data.
+pub fn parse_mtl<R: BufRead>(reader: R) -> Result<Vec<Material>, MeshError>
+{
+    let mut materials: Vec<Material> = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let number = number + 1;
+        let line = line?;
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) => k,
+            None => continue
+        };
+        if keyword == "newmtl" {
+            materials.push(Material::new(&words.collect::<Vec<_>>().join(" ")));
+            continue;
+        }
+        let current = match materials.last_mut() {
+            Some(m) => m,
+            None => return Err(MeshError::Parse(number, format!("'{}' before any newmtl", keyword)))
+        };
+        match keyword {
+            "Ka" | "Kd" | "Ks" => {
+                let v = parse_floats(words, number, 3, 3)?;
+                let color = Pixel::Color(v[0], v[1], v[2]);
+                match keyword {
+                    "Ka" => current.ambient = color,
+                    "Kd" => current.diffuse = color,
+                    _ => current.specular = color
+                }
+            },
+            "Ns" => current.shininess = parse_floats(words, number, 1, 1)?[0],
+            "d" => current.opacity = parse_floats(words, number, 1, 1)?[0],
+            "Tr" => current.opacity = 1.0 - parse_floats(words, number, 1, 1)?[0],
+            // Options may come before the file name, which is always last.
+            "map_Kd" => current.diffuse_map = words.last().map(|w| w.to_string()),
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+/// Loads an OBJ file along with any material libraries it names, which are looked for next to it.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Obj, MeshError>
+{
+    let path = path.as_ref();
+    let mut obj = parse_obj(BufReader::new(File::open(path)?))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for lib in obj.material_libs.clone() {
+        let materials = parse_mtl(BufReader::new(File::open(dir.join(&lib))?))?;
+        obj.materials.extend(materials);
+    }
+    Ok(obj)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn tris(obj: &Obj) -> Vec<(usize, usize, usize)>
+    {
+        obj.mesh.patches.iter().map(|p| match p { &Patch::Tri(a, b, c) => (a, b, c), _ => panic!("expected a triangle") }).collect()
+    }
+
+    // The line of the parse error for `data`.
+    fn error_line(data: &str) -> usize
+    {
+        match parse_obj(data.as_bytes()) {
+            Err(MeshError::Parse(line, _)) => line,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("parsed malformed OBJ")
+        }
+    }
+
+    #[test]
+    fn quad_with_uvs_and_normals()
+    {
+        let data = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 1\nvn 0 0 1\nf 1/1/1 2/1/1 3/2/1 4/2/1\n";
+        let obj = parse_obj(data.as_bytes()).unwrap();
+        assert_eq!(tris(&obj), vec![(0, 1, 2), (0, 2, 3)]);
+        assert_eq!(obj.mesh.uvs[2], Vector2::new(1.0, 1.0));
+        assert_eq!(obj.mesh.normals[3], Vector3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_latest_vertex()
+    {
+        let data = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\nf 1 -3 4\n";
+        let obj = parse_obj(data.as_bytes()).unwrap();
+        assert_eq!(obj.mesh.positions[2], Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(tris(&obj), vec![(0, 1, 2), (0, 1, 3)]);
+        assert_eq!(obj.mesh.positions[3], Vector3::new(5.0, 5.0, 5.0));
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors()
+    {
+        assert_eq!(error_line("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"), 4);
+        assert_eq!(error_line("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n"), 4);
+        assert_eq!(error_line("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -4 1 2\n"), 4);
+        assert_eq!(error_line("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1/2 2 3\n"), 4);
+    }
+
+    #[test]
+    fn vertices_take_3_4_or_6_numbers()
+    {
+        let obj = parse_obj("v 2 4 6 2\nv 1 0 0 1 0.5 0\nv 0 1 0\nf 1 2 3\n".as_bytes()).unwrap();
+        assert_eq!(obj.mesh.positions[0], Vector3::new(1.0, 2.0, 3.0));
+        match obj.mesh.colors[1] {
+            Pixel::Color(r, g, b) => assert_eq!((r, g, b), (1.0, 0.5, 0.0)),
+            _ => panic!("expected a color")
+        }
+        assert_eq!(error_line("v 1 2\n"), 1);
+        assert_eq!(error_line("v 0 0 0\nv 1 2 3 4 5\n"), 2);
+        assert_eq!(error_line("v 1 2 3 4 5 6 7\n"), 1);
+        assert_eq!(error_line("v 1 2 3 0\n"), 1);
+        assert_eq!(error_line("v 1 x 3\n"), 1);
+    }
+}
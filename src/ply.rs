@@ -0,0 +1,392 @@
+extern crate nalgebra;
+
+use mesh::{Mesh, MeshError};
+use render::Patch;
+use Pixel;
+use nalgebra::{Vector2, Vector3};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format
+{
+    Ascii,
+    LittleEndian,
+    BigEndian
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Scalar
+{
+    I8, U8, I16, U16, I32, U32, F32, F64
+}
+
+impl Scalar
+{
+    fn parse(name: &str) -> Option<Scalar>
+    {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None
+        }
+    }
+    fn size(&self) -> usize
+    {
+        match *self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8
+        }
+    }
+}
+
+enum Property
+{
+    Scalar(String, Scalar),
+    /// A list with its length type and item type.
+    List(String, Scalar, Scalar)
+}
+
+struct Element
+{
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+impl Element
+{
+    /// The least data one instance can take up: a token per property in ASCII, or its scalars
+    /// (and list lengths, with empty lists) in bytes.
+    fn min_size(&self, format: Format) -> usize
+    {
+        if format == Format::Ascii {
+            return self.properties.len();
+        }
+        self.properties.iter().map(|p| match p {
+            &Property::Scalar(_, ty) => ty.size(),
+            &Property::List(_, len, _) => len.size()
+        }).sum()
+    }
+    fn has_scalar(&self, name: &str) -> bool
+    {
+        self.properties.iter().any(|p| match p {
+            &Property::Scalar(ref n, _) => n == name,
+            _ => false
+        })
+    }
+}
+
+/// Reads values out of the body of a PLY file, in whichever format it uses.
+struct Body<'a>
+{
+    format: Format,
+    words: str::SplitWhitespace<'a>,
+    data: &'a [u8],
+    at: usize
+}
+
+impl <'a> Body<'a>
+{
+    fn read(&mut self, ty: Scalar) -> Result<f64, MeshError>
+    {
+        if self.format == Format::Ascii {
+            return match self.words.next().map(|w| w.parse()) {
+                Some(Ok(v)) => Ok(v),
+                Some(Err(_)) => Err(MeshError::Format("bad number in PLY data".to_string())),
+                None => Err(MeshError::Format("PLY data ends early".to_string()))
+            };
+        }
+        let size = ty.size();
+        if self.at + size > self.data.len() {
+            return Err(MeshError::Format("PLY data ends early".to_string()));
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(&self.data[self.at..self.at+size]);
+        if self.format == Format::BigEndian {
+            bytes[..size].reverse();
+        }
+        self.at += size;
+        let bits = u64::from_le_bytes(bytes);
+        Ok(match ty {
+            Scalar::I8 => bits as u8 as i8 as f64,
+            Scalar::U8 => bits as u8 as f64,
+            Scalar::I16 => bits as u16 as i16 as f64,
+            Scalar::U16 => bits as u16 as f64,
+            Scalar::I32 => bits as u32 as i32 as f64,
+            Scalar::U32 => bits as u32 as f64,
+            Scalar::F32 => f32::from_bits(bits as u32) as f64,
+            Scalar::F64 => f64::from_bits(bits)
+        })
+    }
+}
+
+fn parse_header(text: &str) -> Result<(Format, Vec<Element>), MeshError>
+{
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let bad = || MeshError::Parse(number, format!("bad header line '{}'", line));
+        match words.get(0).map(|w| *w) {
+            Some("format") => {
+                format = Some(match words.get(1).map(|w| *w) {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::LittleEndian,
+                    Some("binary_big_endian") => Format::BigEndian,
+                    _ => return Err(bad())
+                });
+            },
+            Some("element") => {
+                if words.len() != 3 {
+                    return Err(bad());
+                }
+                elements.push(Element {
+                    name: words[1].to_string(),
+                    count: words[2].parse().map_err(|_| bad())?,
+                    properties: Vec::new()
+                });
+            },
+            Some("property") => {
+                let property = match (words.get(1).map(|w| *w), words.len()) {
+                    (Some("list"), 5) => {
+                        match (Scalar::parse(words[2]), Scalar::parse(words[3])) {
+                            (Some(len), Some(item)) => Property::List(words[4].to_string(), len, item),
+                            _ => return Err(bad())
+                        }
+                    },
+                    (Some(ty), 3) => match Scalar::parse(ty) {
+                        Some(ty) => Property::Scalar(words[2].to_string(), ty),
+                        None => return Err(bad())
+                    },
+                    _ => return Err(bad())
+                };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(bad())
+                }
+            },
+            _ => {}
+        }
+    }
+    match format {
+        Some(format) => Ok((format, elements)),
+        None => Err(MeshError::Format("PLY header has no format line".to_string()))
+    }
+}
+
+/// Parses ASCII or binary PLY data. Vertex positions, normals, texture coordinates and colors are
+/// read; faces are triangulated. Other elements are skipped.
+pub fn parse_ply(data: &[u8]) -> Result<Mesh, MeshError>
+{
+    if !data.starts_with(b"ply") {
+        return Err(MeshError::Format("not a PLY file".to_string()));
+    }
+    let marker = b"end_header";
+    let end = match data.windows(marker.len()).position(|w| w == marker) {
+        Some(i) => i,
+        None => return Err(MeshError::Format("PLY header has no end_header".to_string()))
+    };
+    let header = match str::from_utf8(&data[..end]) {
+        Ok(h) => h,
+        Err(_) => return Err(MeshError::Format("PLY header is not valid text".to_string()))
+    };
+    let (format, elements) = parse_header(header)?;
+
+    // The body starts after the newline that ends the header.
+    let mut start = end + marker.len();
+    while start < data.len() && data[start] != b'\n' {
+        start += 1;
+    }
+    let body = &data[(start+1).min(data.len())..];
+    let text = if format == Format::Ascii {
+        match str::from_utf8(body) {
+            Ok(t) => t,
+            Err(_) => return Err(MeshError::Format("ASCII PLY data is not valid text".to_string()))
+        }
+    } else {
+        ""
+    };
+
+    // Check the counts against the data before reading, so a header can't promise more elements
+    // than there are, or elements that take up no data at all, and keep the loader busy forever.
+    let available = if format == Format::Ascii { text.split_whitespace().count() } else { body.len() };
+    let mut needed: usize = 0;
+    for element in elements.iter() {
+        if element.name == "vertex" && !(element.has_scalar("x") && element.has_scalar("y") && element.has_scalar("z")) {
+            return Err(MeshError::Format("PLY vertex element has no x, y and z properties".to_string()));
+        }
+        if element.count > 0 && element.properties.is_empty() {
+            return Err(MeshError::Format(format!("PLY element '{}' has no properties", element.name)));
+        }
+        needed = element.count.checked_mul(element.min_size(format))
+            .and_then(|n| n.checked_add(needed))
+            .ok_or_else(|| MeshError::Format(format!("PLY element '{}' count is too large", element.name)))?;
+    }
+    if needed > available {
+        return Err(MeshError::Format("PLY header promises more data than there is".to_string()));
+    }
+
+    let mut body = Body {
+        format: format,
+        words: text.split_whitespace(),
+        data: body,
+        at: 0
+    };
+
+    let mut mesh = Mesh::new();
+    for element in elements.iter() {
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        for _ in 0..element.count {
+            let mut values = Vec::new();
+            let mut indices = Vec::new();
+            for property in element.properties.iter() {
+                match property {
+                    &Property::Scalar(ref name, ty) => {
+                        let v = body.read(ty)?;
+                        // Integer colors run from 0 to 255, floating point ones from 0 to 1.
+                        let v = match (name.as_str(), ty) {
+                            ("red", Scalar::U8) | ("green", Scalar::U8) | ("blue", Scalar::U8) => v / 255.0,
+                            _ => v
+                        };
+                        values.push((name.as_str(), v as f32));
+                    },
+                    &Property::List(ref name, len, item) => {
+                        let count = whole(body.read(len)?, "list length")?;
+                        for _ in 0..count {
+                            let v = body.read(item)?;
+                            if name == "vertex_indices" || name == "vertex_index" {
+                                indices.push(whole(v, "vertex index")?);
+                            }
+                        }
+                    }
+                }
+            }
+            let get = |names: &[&str]| {
+                values.iter().find(|&&(n, _)| names.contains(&n)).map(|&(_, v)| v)
+            };
+            if is_vertex {
+                // Checked against the header above.
+                mesh.positions.push(Vector3::new(get(&["x"]).unwrap(), get(&["y"]).unwrap(), get(&["z"]).unwrap()));
+                if let (Some(x), Some(y), Some(z)) = (get(&["nx"]), get(&["ny"]), get(&["nz"])) {
+                    mesh.normals.push(Vector3::new(x, y, z));
+                }
+                if let (Some(u), Some(v)) = (get(&["u", "s", "texture_u"]), get(&["v", "t", "texture_v"])) {
+                    mesh.uvs.push(Vector2::new(u, v));
+                }
+                if let (Some(r), Some(g), Some(b)) = (get(&["red"]), get(&["green"]), get(&["blue"])) {
+                    mesh.colors.push(Pixel::Color(r, g, b));
+                }
+            } else if is_face && indices.len() >= 3 {
+                for i in 1..(indices.len()-1) {
+                    mesh.patches.push(Patch::Tri(indices[0], indices[i], indices[i+1]));
+                }
+            }
+        }
+    }
+    let count = mesh.positions.len();
+    if mesh.patches.iter().any(|p| match p { &Patch::Tri(a, b, c) => a >= count || b >= count || c >= count, _ => false }) {
+        return Err(MeshError::Format("PLY face refers to a missing vertex".to_string()));
+    }
+    Ok(mesh)
+}
+
+// Counts and indices may be stored as any scalar type, but must hold a non-negative whole number.
+fn whole(v: f64, what: &str) -> Result<usize, MeshError>
+{
+    if v >= 0.0 && v.fract() == 0.0 && v <= usize::MAX as f64 {
+        Ok(v as usize)
+    } else {
+        Err(MeshError::Format(format!("PLY {} {} is negative or not a whole number", what, v)))
+    }
+}
+
+pub fn load_ply<P: AsRef<Path>>(path: P) -> Result<Mesh, MeshError>
+{
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    parse_ply(&data)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const HEADER: &'static str = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn error(data: &[u8]) -> String
+    {
+        match parse_ply(data) {
+            Ok(_) => panic!("parsed a malformed file"),
+            Err(e) => e.to_string()
+        }
+    }
+
+    #[test]
+    fn ascii_quad_is_fanned_into_triangles()
+    {
+        let data = format!("{}0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n", HEADER);
+        let mesh = parse_ply(data.as_bytes()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.positions[2], Vector3::new(1.0, 1.0, 0.0));
+        let tris: Vec<_> = mesh.patches.iter().map(|p| match p { &Patch::Tri(a, b, c) => (a, b, c), _ => panic!() }).collect();
+        assert_eq!(tris, vec![(0, 1, 2), (0, 2, 3)]);
+    }
+
+    #[test]
+    fn binary_little_endian_with_colors()
+    {
+        let mut data = b"ply\nformat binary_little_endian 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\nelement face 1\nproperty list uchar uint vertex_indices\nend_header\n".to_vec();
+        let verts = [(0.0f32, 0.0f32, 0.0f32), (1.0, 0.0, 0.0), (0.0, 2.0, 0.0)];
+        for &(x, y, z) in verts.iter() {
+            for v in [x, y, z].iter() {
+                data.extend_from_slice(&v.to_bits().to_le_bytes());
+            }
+            data.extend_from_slice(&[255, 0, 51]);
+        }
+        data.push(3);
+        for i in [0u32, 1, 2].iter() {
+            data.extend_from_slice(&i.to_le_bytes());
+        }
+        let mesh = parse_ply(&data).unwrap();
+        assert_eq!(mesh.positions[2], Vector3::new(0.0, 2.0, 0.0));
+        match mesh.colors[0] {
+            Pixel::Color(r, g, b) => assert_eq!((r, g, b), (1.0, 0.0, 0.2)),
+            _ => panic!("expected a color")
+        }
+        assert_eq!(mesh.patches.len(), 1);
+    }
+
+    #[test]
+    fn bad_indices_are_rejected()
+    {
+        for face in ["3 -1 1 2\n", "3 0 1.5 2\n", "3 0 1 4\n", "-1 0 1 2\n"].iter() {
+            error(format!("{}0 0 0\n1 0 0\n1 1 0\n0 1 0\n{}", HEADER, face).as_bytes());
+        }
+    }
+
+    #[test]
+    fn counts_the_data_cannot_hold_are_rejected()
+    {
+        // Elements without properties take up no data, so their count used to be looped over
+        // without end.
+        error(b"ply\nformat ascii 1.0\nelement junk 18446744073709551615\nend_header\n");
+        error(b"ply\nformat ascii 1.0\nelement vertex 4000000000\nend_header\n");
+        error(b"ply\nformat ascii 1.0\nelement vertex 4000000000\nproperty float x\nproperty float y\nproperty float z\nend_header\n0 0 0\n");
+        error(b"ply\nformat binary_little_endian 1.0\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\nend_header\n\0\0\0\0");
+        error(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nend_header\n0 0\n");
+    }
+}
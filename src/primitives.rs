@@ -0,0 +1,208 @@
+extern crate nalgebra;
+
+use mesh::Mesh;
+use render::Patch;
+use nalgebra::{Vector2, Vector3, Norm, dot, cross};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// Generated meshes wind counter-clockwise when seen from outside, so back faces are culled.
+
+/// Adds a grid of `nu` by `nv` quads. `f` maps a (u, v) pair in [0, 1] to a position and normal,
+/// and must be oriented so that moving along u and then along v turns counter-clockwise seen from
+/// outside. If a whole row of vertices meets at a point (as at the poles of a sphere), the
+/// triangles that would have zero area there are left out.
+fn grid<F>(mesh: &mut Mesh, nu: usize, nv: usize, collapse_bottom: bool, collapse_top: bool, f: F) -> ()
+    where F: Fn(f32, f32) -> (Vector3<f32>, Vector3<f32>)
+{
+    let start = mesh.positions.len();
+    for j in 0..(nv+1) {
+        for i in 0..(nu+1) {
+            let (u, v) = (i as f32 / nu as f32, j as f32 / nv as f32);
+            let (position, normal) = f(u, v);
+            mesh.positions.push(position);
+            mesh.normals.push(normal);
+            mesh.uvs.push(Vector2::new(u, v));
+        }
+    }
+    let index = |i: usize, j: usize| start + j*(nu+1) + i;
+    for j in 0..nv {
+        for i in 0..nu {
+            if !(collapse_bottom && j == 0) {
+                mesh.patches.push(Patch::Tri(index(i, j), index(i+1, j), index(i, j+1)));
+            }
+            if !(collapse_top && j == nv-1) {
+                mesh.patches.push(Patch::Tri(index(i+1, j), index(i+1, j+1), index(i, j+1)));
+            }
+        }
+    }
+}
+
+/// Adds a flat disc at height `y` facing up or down, as a fan around its center.
+fn disc(mesh: &mut Mesh, radius: f32, y: f32, segments: usize, up: bool) -> ()
+{
+    let normal = Vector3::new(0.0, if up { 1.0 } else { -1.0 }, 0.0);
+    let center = mesh.positions.len();
+    mesh.positions.push(Vector3::new(0.0, y, 0.0));
+    mesh.normals.push(normal);
+    mesh.uvs.push(Vector2::new(0.5, 0.5));
+    for i in 0..(segments+1) {
+        let angle = 2.0*PI * i as f32 / segments as f32;
+        let (s, c) = angle.sin_cos();
+        mesh.positions.push(Vector3::new(radius*c, y, -radius*s));
+        mesh.normals.push(normal);
+        mesh.uvs.push(Vector2::new(0.5 + 0.5*c, 0.5 + 0.5*s));
+    }
+    for i in 0..segments {
+        let (a, b) = (center + 1 + i, center + 2 + i);
+        mesh.patches.push(if up { Patch::Tri(center, a, b) } else { Patch::Tri(center, b, a) });
+    }
+}
+
+/// A flat grid in the XZ plane, centered on the origin and facing +y.
+pub fn plane(width: f32, depth: f32, segments_x: usize, segments_z: usize) -> Mesh
+{
+    let mut mesh = Mesh::new();
+    grid(&mut mesh, segments_x.max(1), segments_z.max(1), false, false, |u, v| {
+        (Vector3::new(width*(u - 0.5), 0.0, -depth*(v - 0.5)), Vector3::new(0.0, 1.0, 0.0))
+    });
+    mesh
+}
+
+/// A cube with sides of length `size`, centered on the origin. Each face has its own vertices, so
+/// normals are flat and every face has the full UV square.
+pub fn cube(size: f32) -> Mesh
+{
+    let faces = [
+        (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0)),
+        (Vector3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 0.0)),
+        (Vector3::new(0.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0)),
+        (Vector3::new(0.0, -1.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+        (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)),
+        (Vector3::new(0.0, 0.0, -1.0), Vector3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0))
+    ];
+    let mut mesh = Mesh::new();
+    for &(normal, du, dv) in faces.iter() {
+        grid(&mut mesh, 1, 1, false, false, |u, v| {
+            ((normal*0.5 + du*(u - 0.5) + dv*(v - 0.5)) * size, normal)
+        });
+    }
+    mesh
+}
+
+/// A sphere made of `segments` slices around the y axis and `rings` bands from pole to pole.
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize) -> Mesh
+{
+    let mut mesh = Mesh::new();
+    grid(&mut mesh, segments.max(3), rings.max(2), true, true, |u, v| {
+        let (s, c) = (2.0*PI*u).sin_cos();
+        let (r, y) = ((PI*v).sin(), -(PI*v).cos());
+        let normal = Vector3::new(r*c, y, -r*s);
+        (normal * radius, normal)
+    });
+    mesh
+}
+
+/// A sphere made by splitting each face of an icosahedron into four, `subdivisions` times. The
+/// triangles are much more even than a UV sphere's. UVs are the same spherical mapping as
+/// `uv_sphere`, so textures have a seam where u wraps around.
+pub fn icosphere(radius: f32, subdivisions: usize) -> Mesh
+{
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut points: Vec<Vector3<f32>> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0)
+    ].iter().map(|&(x, y, z)| Vector3::new(x, y, z).normalize()).collect();
+    let mut faces: Vec<(usize, usize, usize)> = vec![
+        (0, 11, 5), (0, 5, 1), (0, 1, 7), (0, 7, 10), (0, 10, 11),
+        (1, 5, 9), (5, 11, 4), (11, 10, 2), (10, 7, 6), (7, 1, 8),
+        (3, 9, 4), (3, 4, 2), (3, 2, 6), (3, 6, 8), (3, 8, 9),
+        (4, 9, 5), (2, 4, 11), (6, 2, 10), (8, 6, 7), (9, 8, 1)
+    ];
+    // Turn every face outwards, whatever order its corners were listed in.
+    for face in faces.iter_mut() {
+        let (a, b, c) = (points[face.0], points[face.1], points[face.2]);
+        if dot(&cross(&(b - a), &(c - a)), &(a + b + c)) < 0.0 {
+            *face = (face.2, face.1, face.0);
+        }
+    }
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: usize, b: usize, points: &mut Vec<Vector3<f32>>| -> usize {
+            let key = (a.min(b), a.max(b));
+            *midpoints.entry(key).or_insert_with(|| {
+                points.push(((points[a] + points[b]) * 0.5).normalize());
+                points.len() - 1
+            })
+        };
+        let mut next = Vec::with_capacity(faces.len() * 4);
+        for &(a, b, c) in faces.iter() {
+            let ab = midpoint(a, b, &mut points);
+            let bc = midpoint(b, c, &mut points);
+            let ca = midpoint(c, a, &mut points);
+            next.push((a, ab, ca));
+            next.push((b, bc, ab));
+            next.push((c, ca, bc));
+            next.push((ab, bc, ca));
+        }
+        faces = next;
+    }
+
+    let mut mesh = Mesh::new();
+    for p in points.iter() {
+        mesh.positions.push(*p * radius);
+        mesh.normals.push(*p);
+        let u = (-p.z).atan2(p.x) / (2.0*PI);
+        mesh.uvs.push(Vector2::new(if u < 0.0 { u + 1.0 } else { u }, (-p.y).max(-1.0).min(1.0).acos() / PI));
+    }
+    for &(a, b, c) in faces.iter() {
+        mesh.patches.push(Patch::Tri(a, b, c));
+    }
+    mesh
+}
+
+/// A closed cylinder around the y axis, centered on the origin.
+pub fn cylinder(radius: f32, height: f32, segments: usize) -> Mesh
+{
+    let segments = segments.max(3);
+    let mut mesh = Mesh::new();
+    grid(&mut mesh, segments, 1, false, false, |u, v| {
+        let (s, c) = (2.0*PI*u).sin_cos();
+        (Vector3::new(radius*c, height*(v - 0.5), -radius*s), Vector3::new(c, 0.0, -s))
+    });
+    disc(&mut mesh, radius, height/2.0, segments, true);
+    disc(&mut mesh, radius, -height/2.0, segments, false);
+    mesh
+}
+
+/// A closed cone around the y axis with its base at `-height/2` and its tip at `height/2`.
+pub fn cone(radius: f32, height: f32, segments: usize) -> Mesh
+{
+    let segments = segments.max(3);
+    let slope = Vector2::new(height, radius).normalize();
+    let mut mesh = Mesh::new();
+    grid(&mut mesh, segments, 1, false, true, |u, v| {
+        let (s, c) = (2.0*PI*u).sin_cos();
+        let r = radius*(1.0 - v);
+        (Vector3::new(r*c, height*(v - 0.5), -r*s), Vector3::new(slope.x*c, slope.y, -slope.x*s))
+    });
+    disc(&mut mesh, radius, -height/2.0, segments, false);
+    mesh
+}
+
+/// A torus around the y axis. `major` is the distance from the center to the middle of the tube
+/// and `minor` the radius of the tube; `segments` go around the y axis and `sides` around the tube.
+pub fn torus(major: f32, minor: f32, segments: usize, sides: usize) -> Mesh
+{
+    let mut mesh = Mesh::new();
+    grid(&mut mesh, segments.max(3), sides.max(3), false, false, |u, v| {
+        let (s, c) = (2.0*PI*u).sin_cos();
+        let (ts, tc) = (2.0*PI*v).sin_cos();
+        let normal = Vector3::new(tc*c, ts, -tc*s);
+        let r = major + minor*tc;
+        (Vector3::new(r*c, minor*ts, -r*s), normal)
+    });
+    mesh
+}
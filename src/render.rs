@@ -1,26 +1,29 @@
 extern crate nalgebra;
 
 use buffer::*;
-use std::ops::{Add,Mul};
+use camera::perspective_divide;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::thread;
 use nalgebra::{Vector4, Vector3, Vector2, Norm, dot, cross};
 
+/// Values that can be interpolated across a patch. `combine` blends a list of values given with
+/// their weights, which sum to 1. The rasterizer lists a patch's vertices in the order the patch
+/// names them, so the first entry is always its first vertex (see `Flat`).
+///
+/// It's implemented for `f32`, `f64`, `Pixel`, nalgebra's `f32` vectors, points and square
+/// matrices, `Flat`, tuples of up to 6 varyings and arrays of 1 to 8. There's no blanket impl for
+/// every type with `Add` and `Mul<f32>`, since it would overlap the tuple and array impls: use the
+/// `impl_varying_ops!` macro for such types, and `impl_varying!` for structs of varyings.
 pub trait Varying
 {
     fn combine(&[(f32, &Self)]) -> Self;
 }
 
-impl <T> Varying for T where T:Add<Output=T> + Mul<f32, Output=T> + Clone
-{
-    fn combine(list: &[(f32, &Self)]) -> Self
-    {
-        let mut acc = list[0].1.clone() * list[0].0;
-        for i in 1..list.len() {
-            acc = acc + list[i].1.clone() * list[i].0;
-        }
-        acc
-    }
-}
-
+/// A primitive, by indices into the vertex positions. Triangles take clip-space positions and
+/// are divided by w; points and lines are placed at their x and y as given, so pass clip-space
+/// positions through `perspective_divide` first.
 pub enum Patch
 {
     Point(usize),
@@ -39,24 +42,127 @@ impl Patch
     }
 }
 
-struct Vec4
+/// Information about the fragment being shaded, available to shaders wrapped in `WithInfo`.
+///
+/// Triangles are shaded as if in 2x2 quads: derivatives are differences across the quad that
+/// contains the pixel, so all four of its pixels see the same values. For points and lines the
+/// derivatives of the varying and of depth are zero, while `position_dx` and `position_dy` are
+/// still the size of a pixel.
+pub struct FragmentInfo<'a, V: 'a>
 {
-    c: [f32; 4]
+    /// The pixel being shaded.
+    pub x: usize,
+    pub y: usize,
+    /// Center of the pixel, in the same [-1, 1] screen coordinates as positions.
+    pub position: Vector2<f32>,
+    pub depth: f32,
+    /// Change in `position` from one pixel to the next along x and along y.
+    pub position_dx: Vector2<f32>,
+    pub position_dy: Vector2<f32>,
+    pub depth_dx: f32,
+    pub depth_dy: f32,
+    /// Index into `patches` of the patch that produced this fragment.
+    pub primitive: usize,
+    /// Weights of the patch's vertices at this fragment. For lines the third weight is zero, and
+    /// points only have the first.
+    pub barycentric: (f32, f32, f32),
+    /// Whether the patch winds counter-clockwise on screen. Always true for points and lines, and
+    /// for triangles unless they're drawn with a `Cull` other than `Cull::Back`.
+    pub front_facing: bool,
+    vertices: [&'a V; 3],
+    weights_dx: [f32; 3],
+    weights_dy: [f32; 3]
 }
-impl Varying for Vec4
+
+impl <'a, V> FragmentInfo<'a, V>
 {
-    fn combine(list: &[(f32, &Self)]) -> Self
+    fn new<T>(buf: &Tile<T>, x: usize, y: usize, depth: f32, primitive: usize, barycentric: (f32, f32, f32), vertices: [&'a V; 3]) -> FragmentInfo<'a, V>
     {
-        Vec4 {
-            c: list.iter().fold([0.0; 4], |acc, &(v, x)| {
-                let mut thing = acc;
-                for i in 0..4 {
-                    thing[i] += v* x.c[i]
-                }
-                thing
-            })
+        FragmentInfo {
+            x: x,
+            y: y,
+            position: pixel_center(buf.width, buf.height, x, y),
+            depth: depth,
+            position_dx: Vector2::new(2.0 / buf.width as f32, 0.0),
+            position_dy: Vector2::new(0.0, 2.0 / buf.height as f32),
+            depth_dx: 0.0,
+            depth_dy: 0.0,
+            primitive: primitive,
+            barycentric: barycentric,
+            front_facing: true,
+            vertices: vertices,
+            weights_dx: [0.0; 3],
+            weights_dy: [0.0; 3]
         }
     }
+
+    /// Undoes the swap of the last two vertices made to rasterize a clockwise triangle.
+    fn unswap(&mut self) -> ()
+    {
+        mem::swap(&mut self.barycentric.1, &mut self.barycentric.2);
+        self.vertices.swap(1, 2);
+        self.weights_dx.swap(1, 2);
+        self.weights_dy.swap(1, 2);
+    }
+}
+
+impl <'a, V: Varying> FragmentInfo<'a, V>
+{
+    /// Change in the interpolated varying from one pixel to the next along x.
+    pub fn dfdx(&self) -> V
+    {
+        V::combine(&[(self.weights_dx[0], self.vertices[0]), (self.weights_dx[1], self.vertices[1]), (self.weights_dx[2], self.vertices[2])])
+    }
+    /// Change in the interpolated varying from one pixel to the next along y.
+    pub fn dfdy(&self) -> V
+    {
+        V::combine(&[(self.weights_dy[0], self.vertices[0]), (self.weights_dy[1], self.vertices[1]), (self.weights_dy[2], self.vertices[2])])
+    }
+}
+
+/// A fragment shader. Any `Fn(&U, &V) -> Option<T>` closure is one; wrap a closure in `WithInfo`
+/// to have it receive a `FragmentInfo` as well.
+pub trait Fragment<U, V, T>
+{
+    fn shade(&self, uniform: &U, varying: &V, info: &FragmentInfo<V>) -> Option<T>;
+
+    /// Whether `shade` looks at its `FragmentInfo`. If not, the rasterizer skips computing
+    /// derivatives.
+    fn wants_info(&self) -> bool
+    {
+        true
+    }
+}
+
+impl <U, V, T, F> Fragment<U, V, T> for F where F: Fn(&U, &V) -> Option<T>
+{
+    fn shade(&self, uniform: &U, varying: &V, _: &FragmentInfo<V>) -> Option<T>
+    {
+        self(uniform, varying)
+    }
+    fn wants_info(&self) -> bool
+    {
+        false
+    }
+}
+
+/// Wraps a `Fn(&U, &V, &FragmentInfo<V>) -> Option<T>` closure as a fragment shader.
+pub struct WithInfo<F>(pub F);
+
+impl <U, V, T, F> Fragment<U, V, T> for WithInfo<F> where F: Fn(&U, &V, &FragmentInfo<V>) -> Option<T>
+{
+    fn shade(&self, uniform: &U, varying: &V, info: &FragmentInfo<V>) -> Option<T>
+    {
+        (self.0)(uniform, varying, info)
+    }
+}
+
+impl <'a, U, V, T, F> Fragment<U, V, T> for &'a WithInfo<F> where F: Fn(&U, &V, &FragmentInfo<V>) -> Option<T>
+{
+    fn shade(&self, uniform: &U, varying: &V, info: &FragmentInfo<V>) -> Option<T>
+    {
+        (self.0)(uniform, varying, info)
+    }
 }
 
 fn line_it((sx, sy): (i32, i32), (ex, ey): (i32, i32)) -> Box<Iterator<Item=(i32, i32, f32)>> {
@@ -76,6 +182,11 @@ fn to_buffer_coord<T>(buf: &Buffer<T>, coord: &Vector4<f32>) -> Vector2<f32>
     Vector2::new(((coord.x+1.0) * (buf.width as f32) / 2.0) - 0.5, ((coord.y+1.0) * (buf.height as f32) / 2.0) - 0.5)
 }
 
+fn pixel_center(width: usize, height: usize, x: usize, y: usize) -> Vector2<f32>
+{
+    Vector2::new((2.0*(x as f32+0.5))/(width as f32) - 1.0, (2.0*(y as f32+0.5))/(height as f32) - 1.0)
+}
+
 fn get_interp(target: &Vector3<f32>, a: &Vector3<f32>, b: &Vector3<f32>, c: &Vector3<f32>) -> (f32, f32, f32)
 {
     let vecs = [a, b, c];
@@ -88,7 +199,7 @@ fn get_interp(target: &Vector3<f32>, a: &Vector3<f32>, b: &Vector3<f32>, c: &Vec
 }
 
 pub fn process<V,I,U,T,E,F>(buf: &mut DepthBuffer<T>, uniform: &U, varying: &Vec<V>, patches: &Vec<Patch>, vertex: E, fragment: F) -> ()
-    where I:Varying, E: Fn(&U,&V) -> (Vector4<f32>, I), F: Fn(&U,&I) -> Option<T>
+    where I:Varying, E: Fn(&U,&V) -> (Vector4<f32>, I), F: Fragment<U, I, T>
 {
     let mut varied = Vec::new();
     let mut pos = Vec::new();
@@ -97,44 +208,469 @@ pub fn process<V,I,U,T,E,F>(buf: &mut DepthBuffer<T>, uniform: &U, varying: &Vec
         varied.push(v);
         pos.push(p);
     }
-    render(buf, uniform, &pos, &varied, patches, fragment) 
+    render(buf, uniform, &pos, &varied, patches, fragment)
 }
 
-pub fn render<V,U,T,F>(buf: &mut DepthBuffer<T>, uniform: &U, positions: &Vec<Vector4<f32>>, varying: &Vec<V>, patches: &Vec<Patch>, fragment: F) -> ()
-    where V:Varying, F: Fn(&U, &V) -> Option<T>
+/// Draws the same mesh once per entry in `instances`. The vertex closure receives the per-vertex
+/// value along with the per-instance value and the instance index.
+pub fn process_instanced<V,N,I,U,T,E,F>(buf: &mut DepthBuffer<T>, uniform: &U, varying: &Vec<V>, instances: &Vec<N>, patches: &Vec<Patch>, vertex: E, fragment: F) -> ()
+    where I:Varying, E: Fn(&U,&V,&N,usize) -> (Vector4<f32>, I), F: Fragment<U, I, T>
+{
+    let mut varied = Vec::with_capacity(varying.len());
+    let mut pos = Vec::with_capacity(varying.len());
+    let mut tile = buf.tile();
+    for (index, instance) in instances.iter().enumerate() {
+        varied.clear();
+        pos.clear();
+        for point in varying {
+            let (p, v) = vertex(uniform, point, instance, index);
+            varied.push(v);
+            pos.push(p);
+        }
+        for (primitive, patch) in patches.iter().enumerate() {
+            render_patch(&mut tile, uniform, &pos, &varied, primitive, patch, Cull::Back, Degenerate::Skip, &fragment);
+        }
+    }
+}
+
+/// A post-transform cache of vertex shader outputs. Only vertices referenced by patches are
+/// shaded, and outputs are reused across draws for as long as the uniform compares equal to the
+/// one they were shaded with. Call `clear` if the vertex data itself changes.
+pub struct VertexCache<U, I>
 {
+    pub hits: usize,
+    pub misses: usize,
+    uniform: Option<U>,
+    slots: Vec<Option<usize>>,
+    positions: Vec<Vector4<f32>>,
+    varied: Vec<I>
+}
+
+impl <U, I> VertexCache<U, I>
+{
+    pub fn new() -> VertexCache<U, I>
+    {
+        VertexCache {
+            hits: 0,
+            misses: 0,
+            uniform: None,
+            slots: Vec::new(),
+            positions: Vec::new(),
+            varied: Vec::new()
+        }
+    }
+    /// Throws away all cached outputs.
+    pub fn clear(&mut self) -> ()
+    {
+        self.uniform = None;
+        self.slots.clear();
+        self.positions.clear();
+        self.varied.clear();
+    }
+    pub fn reset_counters(&mut self) -> ()
+    {
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+impl <U: PartialEq + Clone, I> VertexCache<U, I>
+{
+    fn fetch<V, E>(&mut self, uniform: &U, varying: &Vec<V>, index: usize, vertex: &E) -> usize
+        where E: Fn(&U,&V) -> (Vector4<f32>, I)
+    {
+        if let Some(slot) = self.slots[index] {
+            self.hits += 1;
+            return slot;
+        }
+        self.misses += 1;
+        let (p, v) = vertex(uniform, &varying[index]);
+        self.positions.push(p);
+        self.varied.push(v);
+        self.slots[index] = Some(self.positions.len() - 1);
+        self.positions.len() - 1
+    }
+}
+
+/// Like `process`, but shades vertices through `cache`.
+pub fn process_cached<V,I,U,T,E,F>(buf: &mut DepthBuffer<T>, cache: &mut VertexCache<U, I>, uniform: &U, varying: &Vec<V>, patches: &Vec<Patch>, vertex: E, fragment: F) -> ()
+    where I:Varying, U: PartialEq + Clone, E: Fn(&U,&V) -> (Vector4<f32>, I), F: Fragment<U, I, T>
+{
+    if cache.uniform.as_ref() != Some(uniform) || cache.slots.len() != varying.len() {
+        cache.clear();
+        cache.uniform = Some(uniform.clone());
+        cache.slots = vec![None; varying.len()];
+    }
+    let mut remapped = Vec::with_capacity(patches.len());
     for patch in patches {
-        match patch {
-            &Patch::Point(index) => {
-                let pos = positions[index];
-                if let Some((x, y)) = buf.center_to_xy(pos.x, pos.y) {
-                    if let Some(val) = fragment(uniform, &varying[index]) {
-                        buf.apply(x, y, (val, positions[index].z));
+        remapped.push(match patch {
+            &Patch::Point(a) => Patch::Point(cache.fetch(uniform, varying, a, &vertex)),
+            &Patch::Line(a, b) => {
+                let a = cache.fetch(uniform, varying, a, &vertex);
+                Patch::Line(a, cache.fetch(uniform, varying, b, &vertex))
+            },
+            &Patch::Tri(a, b, c) => {
+                let a = cache.fetch(uniform, varying, a, &vertex);
+                let b = cache.fetch(uniform, varying, b, &vertex);
+                Patch::Tri(a, b, cache.fetch(uniform, varying, c, &vertex))
+            }
+        });
+    }
+    render(buf, uniform, &cache.positions, &cache.varied, &remapped, fragment)
+}
+
+pub fn render<V,U,T,F>(buf: &mut DepthBuffer<T>, uniform: &U, positions: &Vec<Vector4<f32>>, varying: &Vec<V>, patches: &Vec<Patch>, fragment: F) -> ()
+    where V:Varying, F: Fragment<U, V, T>
+{
+    render_with(buf, uniform, positions, varying, patches, Cull::Back, Degenerate::Skip, fragment)
+}
+
+/// Which triangles aren't drawn, by their winding on screen. Front faces wind counter-clockwise.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Cull
+{
+    /// Draw only counter-clockwise triangles.
+    Back,
+    /// Draw only clockwise triangles.
+    Front,
+    /// Draw both; `FragmentInfo::front_facing` tells them apart.
+    None
+}
+
+impl Default for Cull
+{
+    fn default() -> Cull
+    {
+        Cull::Back
+    }
+}
+
+/// What to do with triangles that have no area on screen or aren't made of finite numbers.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Degenerate
+{
+    /// Draw nothing.
+    Skip,
+    /// Draw the triangle's edges as lines instead, so slivers seen edge-on don't vanish. Edges
+    /// with an end behind the viewer, or NaN or infinite ends, are still skipped.
+    Lines
+}
+
+impl Default for Degenerate
+{
+    fn default() -> Degenerate
+    {
+        Degenerate::Skip
+    }
+}
+
+/// Like `render`, choosing which triangles are culled and how degenerate triangles are drawn.
+pub fn render_with<V,U,T,F>(buf: &mut DepthBuffer<T>, uniform: &U, positions: &Vec<Vector4<f32>>, varying: &Vec<V>, patches: &Vec<Patch>, cull: Cull, degenerate: Degenerate, fragment: F) -> ()
+    where V:Varying, F: Fragment<U, V, T>
+{
+    let mut tile = buf.tile();
+    for (primitive, patch) in patches.iter().enumerate() {
+        render_patch(&mut tile, uniform, positions, varying, primitive, patch, cull, degenerate, &fragment);
+    }
+}
+
+/// Why a draw was rejected by `try_render` or `try_process`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenderError
+{
+    /// A patch (by its index in `patches`) names a vertex that doesn't exist.
+    IndexOutOfRange(usize, usize),
+    /// There are no vertices to draw.
+    EmptyVertices,
+    /// The number of positions and of varyings differ.
+    MismatchedLengths(usize, usize),
+    /// A vertex position (by index) has a NaN or infinite component.
+    InvalidPosition(usize),
+    /// The buffer has no pixels.
+    EmptyBuffer,
+    /// A pixel outside of the buffer was accessed.
+    OutOfBounds(usize, usize)
+}
+
+impl fmt::Display for RenderError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            &RenderError::IndexOutOfRange(patch, index) => write!(f, "patch {} refers to missing vertex {}", patch, index),
+            &RenderError::EmptyVertices => write!(f, "no vertices to draw"),
+            &RenderError::MismatchedLengths(positions, varying) => write!(f, "{} positions but {} varyings", positions, varying),
+            &RenderError::InvalidPosition(index) => write!(f, "vertex {} has a NaN or infinite position", index),
+            &RenderError::EmptyBuffer => write!(f, "buffer has no pixels"),
+            &RenderError::OutOfBounds(x, y) => write!(f, "pixel ({}, {}) is outside of the buffer", x, y)
+        }
+    }
+}
+
+impl Error for RenderError {}
+
+/// Checks that a draw can't index out of range or feed the rasterizer bad positions.
+pub fn validate<T, V>(buf: &DepthBuffer<T>, positions: &Vec<Vector4<f32>>, varying: &Vec<V>, patches: &Vec<Patch>) -> Result<(), RenderError>
+{
+    if buf.width == 0 || buf.height == 0 {
+        return Err(RenderError::EmptyBuffer);
+    }
+    if positions.is_empty() {
+        return Err(RenderError::EmptyVertices);
+    }
+    if positions.len() != varying.len() {
+        return Err(RenderError::MismatchedLengths(positions.len(), varying.len()));
+    }
+    for (primitive, patch) in patches.iter().enumerate() {
+        let indices = match patch {
+            &Patch::Point(a) => [a, a, a],
+            &Patch::Line(a, b) => [a, b, b],
+            &Patch::Tri(a, b, c) => [a, b, c]
+        };
+        for &index in indices.iter() {
+            if index >= positions.len() {
+                return Err(RenderError::IndexOutOfRange(primitive, index));
+            }
+        }
+    }
+    for (index, p) in positions.iter().enumerate() {
+        if !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite() && p.w.is_finite()) {
+            return Err(RenderError::InvalidPosition(index));
+        }
+    }
+    Ok(())
+}
+
+/// Like `render`, but checks the draw with `validate` first and draws nothing if it fails.
+pub fn try_render<V,U,T,F>(buf: &mut DepthBuffer<T>, uniform: &U, positions: &Vec<Vector4<f32>>, varying: &Vec<V>, patches: &Vec<Patch>, fragment: F) -> Result<(), RenderError>
+    where V:Varying, F: Fragment<U, V, T>
+{
+    validate(buf, positions, varying, patches)?;
+    render(buf, uniform, positions, varying, patches, fragment);
+    Ok(())
+}
+
+/// Like `process`, but checks the shaded vertices with `validate` before drawing.
+pub fn try_process<V,I,U,T,E,F>(buf: &mut DepthBuffer<T>, uniform: &U, varying: &Vec<V>, patches: &Vec<Patch>, vertex: E, fragment: F) -> Result<(), RenderError>
+    where I:Varying, E: Fn(&U,&V) -> (Vector4<f32>, I), F: Fragment<U, I, T>
+{
+    let mut varied = Vec::with_capacity(varying.len());
+    let mut pos = Vec::with_capacity(varying.len());
+    for point in varying {
+        let (p, v) = vertex(uniform, point);
+        varied.push(v);
+        pos.push(p);
+    }
+    try_render(buf, uniform, &pos, &varied, patches, fragment)
+}
+
+/// Settings for the tiled rasterizer. The buffer is split into bands of `tile_height` rows which
+/// are rasterized across `threads` threads.
+#[derive(Copy, Clone, Debug)]
+pub struct Tiling
+{
+    pub tile_height: usize,
+    pub threads: usize,
+    pub cull: Cull,
+    pub degenerate: Degenerate
+}
+
+impl Default for Tiling
+{
+    fn default() -> Tiling
+    {
+        Tiling {
+            tile_height: 16,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            cull: Cull::Back,
+            degenerate: Degenerate::Skip
+        }
+    }
+}
+
+/// Like `process`, but rasterizes with `render_tiled`.
+pub fn process_tiled<V,I,U,T,E,F>(buf: &mut DepthBuffer<T>, tiling: &Tiling, uniform: &U, varying: &Vec<V>, patches: &Vec<Patch>, vertex: E, fragment: F) -> ()
+    where I:Varying + Sync, U: Sync, T: Send, E: Fn(&U,&V) -> (Vector4<f32>, I), F: Fragment<U, I, T> + Sync
+{
+    let mut varied = Vec::new();
+    let mut pos = Vec::new();
+    for point in varying {
+        let (p, v) = vertex(uniform, point);
+        varied.push(v);
+        pos.push(p);
+    }
+    render_tiled(buf, tiling, uniform, &pos, &varied, patches, fragment)
+}
+
+/// Like `render`, but bins the patches into tiles and rasterizes the tiles in parallel. Each tile
+/// draws its patches in their original order, so the result is identical to `render`.
+pub fn render_tiled<V,U,T,F>(buf: &mut DepthBuffer<T>, tiling: &Tiling, uniform: &U, positions: &Vec<Vector4<f32>>, varying: &Vec<V>, patches: &Vec<Patch>, fragment: F) -> ()
+    where V:Varying + Sync, U: Sync, T: Send, F: Fragment<U, V, T> + Sync
+{
+    let threads = tiling.threads.max(1);
+    let rows = tiling.tile_height.max(1);
+    let height = buf.height;
+    let tiles = buf.tiles(rows);
+    let mut bins = vec![Vec::new(); tiles.len()];
+    for (i, patch) in patches.iter().enumerate() {
+        let (lo, hi) = patch_rows(height, positions, patch);
+        for bin in (lo/rows)..((hi/rows)+1).min(bins.len()) {
+            bins[bin].push(i);
+        }
+    }
+
+    let mut groups: Vec<Vec<_>> = (0..threads).map(|_| Vec::new()).collect();
+    for (i, tile) in tiles.into_iter().enumerate() {
+        groups[i % threads].push((tile, &bins[i]));
+    }
+    let fragment = &fragment;
+    let (cull, degenerate) = (tiling.cull, tiling.degenerate);
+    thread::scope(|scope| {
+        for group in groups {
+            scope.spawn(move || {
+                for (mut tile, bin) in group {
+                    for &i in bin.iter() {
+                        render_patch(&mut tile, uniform, positions, varying, i, &patches[i], cull, degenerate, fragment);
                     }
                 }
-            },
-            &Patch::Line(i_a, i_b) => {
-                let pos_a = positions[i_a];
-                let pos_b = positions[i_b];
-                if let (Some((ax, ay)), Some((bx,by))) = (buf.center_to_xy(pos_a.x,pos_a.y), buf.center_to_xy(pos_b.x,pos_b.y)) {
-                    for (x, y, d) in line_it((ax as i32,ay as i32),(bx as i32,by as i32)) {
-                        //println!("{} {}", x, y);
-                        let loc = Vector4::combine(&[(d, &positions[i_b]), (1.0 - d, &positions[i_a])]);
-                        if let Some(val) = fragment(uniform, &V::combine(&vec![(d,&varying[i_b]),(1.0 - d, &varying[i_a])])) {
-                            buf.apply(x as usize, y as usize, (val, loc.z));
-                        }
+            });
+        }
+    });
+}
+
+/// A conservative range of rows that `patch` may touch.
+fn patch_rows(height: usize, positions: &Vec<Vector4<f32>>, patch: &Patch) -> (usize, usize)
+{
+    let row = |y: f32| {
+        ((y.max(-1.0).min(1.0)+1.0)*height as f32/2.0) as usize
+    };
+    let verts = match patch {
+        &Patch::Point(index) => vec![positions[index]],
+        &Patch::Line(i_a, i_b) => vec![positions[i_a], positions[i_b]],
+        &Patch::Tri(i_a, i_b, i_c) => {
+            let verts = vec![positions[i_a], positions[i_b], positions[i_c]];
+            if verts.iter().any(|v| !(v.w > 0.0)) {
+                return (0, height);
+            }
+            verts.iter().map(|v| Vector4::new(v.x/v.w, v.y/v.w, v.z, 1.0)).collect()
+        }
+    };
+    let (lo, hi) = verts.iter().fold((1.0f32, -1.0f32), |(lo, hi), v| (lo.min(v.y), hi.max(v.y)));
+    (row(lo).saturating_sub(1), row(hi) + 1)
+}
+
+/// The pixel a point or line end lands on. Unlike triangles these aren't divided by w; points
+/// with NaN or infinite coordinates aren't drawn.
+fn screen_xy<T>(buf: &Tile<T>, pos: &Vector4<f32>) -> Option<(usize, usize)>
+{
+    if pos.x.is_finite() && pos.y.is_finite() && pos.z.is_finite() {
+        buf.center_to_xy(pos.x, pos.y)
+    } else {
+        None
+    }
+}
+
+fn render_patch<V,U,T,F>(buf: &mut Tile<T>, uniform: &U, positions: &Vec<Vector4<f32>>, varying: &Vec<V>, primitive: usize, patch: &Patch, cull: Cull, degenerate: Degenerate, fragment: &F) -> ()
+    where V:Varying, F: Fragment<U, V, T>
+{
+    match patch {
+        &Patch::Point(index) => {
+            let pos = positions[index];
+            if let Some((x, y)) = screen_xy(buf, &pos) {
+                if buf.contains(x, y) {
+                    let v = &varying[index];
+                    let info = FragmentInfo::new(buf, x, y, pos.z, primitive, (1.0, 0.0, 0.0), [v, v, v]);
+                    if let Some(val) = fragment.shade(uniform, v, &info) {
+                        buf.apply(x, y, (val, pos.z));
                     }
                 }
-            },
-            &Patch::Tri(i_a, i_b, i_c) => {
-                render_tri(buf, uniform, &[positions[i_a].clone(), positions[i_b].clone(), positions[i_c].clone()], &[&varying[i_a], &varying[i_b], &varying[i_c]], &fragment);
             }
+        },
+        &Patch::Line(i_a, i_b) => {
+            render_line(buf, uniform, primitive, [positions[i_a], positions[i_b]], [&varying[i_a], &varying[i_b]], None, fragment);
+        },
+        &Patch::Tri(i_a, i_b, i_c) => {
+            let verts = [positions[i_a].clone(), positions[i_b].clone(), positions[i_c].clone()];
+            if !is_degenerate(&verts) {
+                let (a, b, c) = (&varying[i_a], &varying[i_b], &varying[i_c]);
+                let front_facing = is_front_facing(&verts);
+                match (cull, front_facing) {
+                    (Cull::Back, false) | (Cull::Front, true) => (),
+                    (_, true) => render_tri(buf, uniform, &verts, &[a, b, c], primitive, true, fragment),
+                    // The rasterizer only fills counter-clockwise triangles, so swap the last two
+                    // vertices. The first one is kept, as it's the provoking vertex.
+                    (_, false) => render_tri(buf, uniform, &[verts[0], verts[2], verts[1]], &[a, c, b], primitive, false, fragment)
+                }
+            } else if degenerate == Degenerate::Lines {
+                // Triangle positions are in clip space, unlike those of lines. Every edge keeps
+                // the triangle's first vertex as its provoking vertex.
+                let ends = [(i_a, i_b), (i_b, i_c), (i_c, i_a)];
+                for &(i, j) in ends.iter() {
+                    if let (Some(a), Some(b)) = (perspective_divide(&positions[i]), perspective_divide(&positions[j])) {
+                        render_line(buf, uniform, primitive, [a, b], [&varying[i], &varying[j]], Some(&varying[i_a]), fragment);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws a line between two positions. The provoking vertex is the first end unless another one
+/// is given, which is blended in with no weight so that it comes first for `Flat` varyings.
+fn render_line<V,U,T,F>(buf: &mut Tile<T>, uniform: &U, primitive: usize, pos: [Vector4<f32>; 2], varying: [&V; 2], provoking: Option<&V>, fragment: &F) -> ()
+    where V:Varying, F: Fragment<U, V, T>
+{
+    if let (Some((ax, ay)), Some((bx,by))) = (screen_xy(buf, &pos[0]), screen_xy(buf, &pos[1])) {
+        for (x, y, d) in line_it((ax as i32,ay as i32),(bx as i32,by as i32)) {
+            if !buf.contains(x as usize, y as usize) {
+                continue
+            }
+            let loc = Vector4::combine(&[(1.0 - d, &pos[0]), (d, &pos[1])]);
+            let info = FragmentInfo::new(buf, x as usize, y as usize, loc.z, primitive, (1.0 - d, d, 0.0), [varying[0], varying[1], varying[1]]);
+            let varied = match provoking {
+                Some(p) => V::combine(&[(0.0, p), (1.0 - d, varying[0]), (d, varying[1])]),
+                None => V::combine(&[(1.0 - d, varying[0]), (d, varying[1])])
+            };
+            if let Some(val) = fragment.shade(uniform, &varied, &info) {
+                buf.apply(x as usize, y as usize, (val, loc.z));
+            }
+        }
+    }
+}
+
+/// Whether a triangle has no area on screen, or coordinates that aren't finite numbers, so that
+/// `render_tri` can't rasterize it.
+fn is_degenerate(verts: &[Vector4<f32>; 3]) -> bool
+{
+    if verts.iter().any(|v| !(v.x.is_finite() && v.y.is_finite() && v.z.is_finite() && v.w.is_finite())) {
+        return true;
+    }
+    let mut denom = 0.0;
+    for i in 0..3 {
+        let (a, b, c) = (verts[i], verts[(i+1)%3], verts[(i+2)%3]);
+        // The edge directions get normalized, so they must have a usable length.
+        let t = b.w*Vector2::new(c.x, c.y) - c.w*Vector2::new(b.x, b.y);
+        if !t.norm_squared().is_normal() {
+            return true;
         }
+        denom += a.w*(c.x*b.y - b.x*c.y);
     }
+    !denom.is_normal()
 }
 
-fn render_tri<T, U, V, F>(buf: &mut DepthBuffer<T>, uniform: &U, verts: &[Vector4<f32>; 3], varying: &[&V; 3], fragment: &F) -> ()
-    where V:Varying, F: Fn(&U,&V) -> Option<T>
+/// Whether a triangle winds counter-clockwise on screen. This is the sign of the determinant of
+/// its (x, y, w) coordinates, which also holds for vertices behind the viewer.
+fn is_front_facing(verts: &[Vector4<f32>; 3]) -> bool
+{
+    let (a, b, c) = (verts[0], verts[1], verts[2]);
+    let det = a.x*(b.y*c.w - b.w*c.y) - a.y*(b.x*c.w - b.w*c.x) + a.w*(b.x*c.y - b.y*c.x);
+    det > 0.0
+}
+
+/// Fills a counter-clockwise triangle. If `front_facing` is false, the caller swapped its last two
+/// vertices to get here, and they're swapped back in the `FragmentInfo`.
+fn render_tri<T, U, V, F>(buf: &mut Tile<T>, uniform: &U, verts: &[Vector4<f32>; 3], varying: &[&V; 3], primitive: usize, front_facing: bool, fragment: &F) -> ()
+    where V:Varying, F: Fragment<U, V, T>
 {
 //    println!("start");
     let mut norms = [Vector2::new(0.0,0.0); 3];
@@ -154,6 +690,10 @@ fn render_tri<T, U, V, F>(buf: &mut DepthBuffer<T>, uniform: &U, verts: &[Vector
     let vec = Vector2::new(-y/denom, x/denom);
     let tar = verts.iter().fold(verts[0], |a, b| { if a.z.abs() > b.z.abs() { a } else { b.clone()}});
     let num = (1.0 - dot(&vec, &Vector2::new(tar.x, tar.y)))/tar.w;
+    if !(vec.x.is_finite() && vec.y.is_finite() && num.is_finite()) {
+        // Too close to degenerate for the plane equation to hold up.
+        return;
+    }
 
     let (a,b,c) = (
         Vector3::new(verts[0].x, verts[0].y, verts[0].w),
@@ -204,6 +744,24 @@ fn render_tri<T, U, V, F>(buf: &mut DepthBuffer<T>, uniform: &U, verts: &[Vector
         Vector3::new(verts[0].x, verts[0].y, verts[0].w),
         Vector3::new(verts[1].x, verts[1].y, verts[1].w),
         Vector3::new(verts[2].x, verts[2].y, verts[2].w));
+
+    // Perspective-correct interpolation weights at the center of pixel (i, j). `get_interp` is
+    // linear in its target, so each weight is a plane over the screen scaled by `val`, and the
+    // planes are found once per triangle.
+    let (width, height) = (buf.width, buf.height);
+    let plane = get_interp(&Vector3::new(0.0, 0.0, 1.0), &a, &b, &c);
+    let plane_x = get_interp(&Vector3::new(1.0, 0.0, 0.0), &a, &b, &c);
+    let plane_y = get_interp(&Vector3::new(0.0, 1.0, 0.0), &a, &b, &c);
+    let weights = |i: usize, j: usize| {
+        let screen = pixel_center(width, height, i, j);
+        let val = (dot(&vec, &screen)+num).recip();
+        [(plane.0 + plane_x.0*screen.x + plane_y.0*screen.y)*val,
+         (plane.1 + plane_x.1*screen.x + plane_y.1*screen.y)*val,
+         (plane.2 + plane_x.2*screen.x + plane_y.2*screen.y)*val]
+    };
+    // The quad derivatives were last found for, and what they were.
+    let mut quad: Option<((usize, usize), [f32; 3], [f32; 3])> = None;
+
     let mut x = anchor.1;
     let mut y = things[anchor.0].y;
     let mut h = 0.0;
@@ -228,12 +786,13 @@ fn render_tri<T, U, V, F>(buf: &mut DepthBuffer<T>, uniform: &U, verts: &[Vector
             break
         }
 
-        for i in (0.5+(x+1.0)*buf.width as f32/2.0) as usize..(0.5+(fx+1.0)*buf.width as f32/2.0) as usize {
-            let loc_x = (2.0*(i as f32+0.5))/(buf.width as f32) - 1.0;
+        let (left, right) = (buf.rect.x, buf.rect.x + buf.rect.w);
+        let (bottom, top) = (buf.rect.y, buf.rect.y + buf.rect.h);
+        for i in ((0.5+(x+1.0)*buf.width as f32/2.0) as usize).max(left)..((0.5+(fx+1.0)*buf.width as f32/2.0) as usize).min(right) {
+            let loc_x = pixel_center(width, height, i, 0).x;
             let (sy, ey) = (y+bm*(loc_x-x), h+y+tm*(loc_x-x));
-            for j in (0.5+(sy+1.0)*buf.height as f32/2.0) as usize..(0.5+(ey+1.0)*buf.height as f32/2.0) as usize {
-                let loc_y = (2.0*(j as f32+0.5))/(buf.height as f32) - 1.0;
-                let screen = Vector2::new(loc_x, loc_y);
+            for j in ((0.5+(sy+1.0)*buf.height as f32/2.0) as usize).max(bottom)..((0.5+(ey+1.0)*buf.height as f32/2.0) as usize).min(top) {
+                let screen = pixel_center(width, height, i, j);
             let mut within = true;
             for i in 0..3 {
                 if dot(&screen, &norms[i]) + offsets[i] > 0.0 {
@@ -248,8 +807,33 @@ fn render_tri<T, U, V, F>(buf: &mut DepthBuffer<T>, uniform: &U, verts: &[Vector
                 let pos = Vector3::new(screen.x*val, screen.y*val, val);
                 let interp = get_interp(&pos, &a, &b, &c);
                 let varied = V::combine(&[(interp.0,varying[0]), (interp.1,varying[1]), (interp.2,varying[2])]);
-                if let Some(v) = fragment(uniform, &varied) {
-                    buf.apply(i, j, (v, verts[0].z*interp.0 + verts[1].z*interp.1+ verts[2].z*interp.2));
+                let depth = verts[0].z*interp.0 + verts[1].z*interp.1+ verts[2].z*interp.2;
+                let mut info = FragmentInfo::new(buf, i, j, depth, primitive, interp, *varying);
+                info.front_facing = front_facing;
+                if fragment.wants_info() {
+                    // Derivatives are taken across the 2x2 quad containing this pixel. Columns
+                    // are filled bottom to top, so pixels of the same quad come in pairs.
+                    let (qi, qj) = (i & !1, j & !1);
+                    let (weights_dx, weights_dy) = match quad {
+                        Some((q, dx, dy)) if q == (qi, qj) => (dx, dy),
+                        _ => {
+                            let (origin, right, up) = (weights(qi, qj), weights(qi+1, qj), weights(qi, qj+1));
+                            let dx = [right[0] - origin[0], right[1] - origin[1], right[2] - origin[2]];
+                            let dy = [up[0] - origin[0], up[1] - origin[1], up[2] - origin[2]];
+                            quad = Some(((qi, qj), dx, dy));
+                            (dx, dy)
+                        }
+                    };
+                    info.weights_dx = weights_dx;
+                    info.weights_dy = weights_dy;
+                    info.depth_dx = (0..3).fold(0.0, |acc, k| acc + verts[k].z*info.weights_dx[k]);
+                    info.depth_dy = (0..3).fold(0.0, |acc, k| acc + verts[k].z*info.weights_dy[k]);
+                }
+                if !front_facing {
+                    info.unswap();
+                }
+                if let Some(v) = fragment.shade(uniform, &varied, &info) {
+                    buf.apply(i, j, (v, depth));
                 }
             }
         }
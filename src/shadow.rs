@@ -0,0 +1,67 @@
+extern crate nalgebra;
+
+use buffer::*;
+use render::*;
+use texture::*;
+use std::f32;
+use nalgebra::Vector4;
+
+/// A depth map rendered from a light's point of view, used to find out whether points in the main
+/// pass can see the light.
+pub struct ShadowMap
+{
+    pub depth: DepthBuffer<()>,
+    /// Allowance added to a point's depth before comparing it against the map, to keep surfaces
+    /// from shadowing themselves.
+    pub bias: f32,
+    /// Radius, in texels, of the percentage-closer filter. 0 takes a single sample.
+    pub pcf: usize
+}
+
+impl ShadowMap
+{
+    pub fn new(width: usize, height: usize) -> ShadowMap
+    {
+        ShadowMap {
+            depth: Buffer::new(width, height, None),
+            bias: 0.01,
+            pcf: 1
+        }
+    }
+    pub fn clear(&mut self) -> ()
+    {
+        self.depth.clear();
+    }
+    /// Renders the depth of the given geometry into the map. `vertex` should place vertices in
+    /// the light's clip space.
+    pub fn render<V, U, E>(&mut self, uniform: &U, varying: &Vec<V>, patches: &Vec<Patch>, vertex: E) -> ()
+        where E: Fn(&U, &V) -> Vector4<f32>
+    {
+        process(&mut self.depth, uniform, varying, patches, |u: &U, v: &V| (vertex(u, v), 0.0), |_: &U, _: &f32| Some(()));
+    }
+    /// How lit a point is, from 0 (fully shadowed) to 1 (fully lit). `position` is the point in
+    /// the light's clip space, as produced by the vertex closure given to `render`. Points outside
+    /// of the map are considered lit.
+    pub fn visibility(&self, position: &Vector4<f32>) -> f32
+    {
+        let (width, height) = (self.depth.width, self.depth.height);
+        let (x, y) = (position.x / position.w, position.y / position.w);
+        if !(-1.0 <= x && x <= 1.0 && -1.0 <= y && y <= 1.0) || width == 0 || height == 0 {
+            return 1.0;
+        }
+        let tx = (((x+1.0)/2.0 * width as f32) as i32).min(width as i32 - 1);
+        let ty = (((y+1.0)/2.0 * height as f32) as i32).min(height as i32 - 1);
+        let view = self.depth.depth_view(f32::NEG_INFINITY);
+        let sampler = Sampler::new(Filter::Nearest, Wrap::Clamp, f32::NEG_INFINITY);
+        let r = self.pcf as i32;
+        let mut lit = 0;
+        for j in -r..(r+1) {
+            for i in -r..(r+1) {
+                if position.z + self.bias >= sampler.texel(&view, tx+i, ty+j) {
+                    lit += 1;
+                }
+            }
+        }
+        lit as f32 / ((2*r+1)*(2*r+1)) as f32
+    }
+}
@@ -0,0 +1,196 @@
+extern crate nalgebra;
+
+use buffer::DepthBuffer;
+use camera::transform;
+use starfield::Starfield;
+use Pixel;
+use nalgebra::{Vector3, Vector4, Matrix4};
+use std::f32::consts::PI;
+
+/// The Julian date of a moment given in UTC. `hours` may include a fraction.
+pub fn julian_date(year: i32, month: u32, day: u32, hours: f64) -> f64
+{
+    // Fliegel and Van Flandern's day count for the Gregorian calendar.
+    let (y, m, d) = (year as i64, month as i64, day as i64);
+    let a = (m - 14) / 12;
+    let day_number = (1461 * (y + 4800 + a)) / 4 + (367 * (m - 2 - 12 * a)) / 12 - (3 * ((y + 4900 + a) / 100)) / 4 + d - 32075;
+    day_number as f64 - 0.5 + hours / 24.0
+}
+
+/// The Julian date of a Unix timestamp in seconds.
+pub fn unix_to_julian(seconds: f64) -> f64
+{
+    seconds / 86400.0 + 2440587.5
+}
+
+/// Greenwich mean sidereal time at Julian date `jd`, in radians from 0 to 2π.
+pub fn greenwich_sidereal_time(jd: f64) -> f32
+{
+    let d = jd - 2451545.0;
+    let t = d / 36525.0;
+    let degrees = 280.46061837 + 360.98564736629 * d + 0.000387933 * t * t - t * t * t / 38710000.0;
+    (degrees.rem_euclid(360.0) as f32).to_radians()
+}
+
+/// How the sky is laid out on screen.
+pub enum SkyProjection
+{
+    /// Through a camera, with the given view and projection matrix. The horizontal frame has east
+    /// on +x, the zenith on +y and north on -z, so an unrotated camera faces north.
+    Camera(Matrix4<f32>),
+    /// The whole sky in a circle, as seen lying on your back with your head to the north: the
+    /// zenith is in the middle, the horizon is the edge, north is up and east is left. The value
+    /// is the aspect ratio of the viewport, so the circle stays round.
+    Dome(f32),
+    /// Azimuth across the screen from north through east, south and west back to north, and
+    /// altitude from the horizon at the bottom to the zenith at the top.
+    Panorama
+}
+
+/// The sky seen by an observer at a place on Earth and a moment in time.
+pub struct Sky
+{
+    /// In degrees, north positive.
+    pub latitude: f32,
+    /// In degrees, east positive.
+    pub longitude: f32,
+    pub julian_date: f64,
+    /// Altitude in radians below which stars are hidden.
+    pub horizon: f32
+}
+
+impl Sky
+{
+    pub fn new(latitude: f32, longitude: f32, julian_date: f64) -> Sky
+    {
+        Sky {
+            latitude: latitude,
+            longitude: longitude,
+            julian_date: julian_date,
+            horizon: 0.0
+        }
+    }
+    /// Local sidereal time in radians: the right ascension on the meridian.
+    pub fn local_sidereal_time(&self) -> f32
+    {
+        (greenwich_sidereal_time(self.julian_date) + self.longitude.to_radians()).rem_euclid(2.0 * PI)
+    }
+    /// Rotates directions from the equatorial frame of `equatorial_to_vector` into the horizontal
+    /// frame, with east on +x, the zenith on +y and north on -z.
+    pub fn rotation(&self) -> Matrix4<f32>
+    {
+        let (ls, lc) = self.local_sidereal_time().sin_cos();
+        let (ps, pc) = self.latitude.to_radians().sin_cos();
+        // First turn the sky so the meridian is on +x and west on +z, then tip the pole down to
+        // the observer's latitude.
+        Matrix4::new(
+            -ls, 0.0, -lc, 0.0,
+            pc * lc, ps, -pc * ls, 0.0,
+            ps * lc, -pc, -ps * ls, 0.0,
+            0.0, 0.0, 0.0, 1.0)
+    }
+    pub fn to_horizontal(&self, direction: &Vector3<f32>) -> Vector3<f32>
+    {
+        let p = transform(&self.rotation(), direction);
+        Vector3::new(p.x, p.y, p.z)
+    }
+    /// Altitude above the horizon and azimuth east of north, both in radians, of a direction in
+    /// the equatorial frame.
+    pub fn alt_az(&self, direction: &Vector3<f32>) -> (f32, f32)
+    {
+        let h = self.to_horizontal(direction);
+        horizontal_alt_az(&h)
+    }
+    /// Draws the stars of `stars` that are above the horizon. Their positions are taken as
+    /// directions in the equatorial frame.
+    pub fn render(&self, stars: &Starfield, buf: &mut DepthBuffer<Pixel>, projection: &SkyProjection, time: f32) -> ()
+    {
+        let rotation = self.rotation();
+        let horizon = self.horizon;
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        stars.render_projected(buf, &origin, time, |star| {
+            let p = transform(&rotation, &star.position);
+            let h = Vector3::new(p.x, p.y, p.z);
+            let (alt, az) = horizontal_alt_az(&h);
+            if alt < horizon {
+                return None;
+            }
+            Some(match projection {
+                &SkyProjection::Camera(ref m) => transform(m, &h),
+                &SkyProjection::Dome(aspect) => {
+                    let r = 1.0 - alt / (PI / 2.0);
+                    let (x, y) = (-r * az.sin(), r * az.cos());
+                    if aspect > 1.0 {
+                        Vector4::new(x / aspect, y, alt, 1.0)
+                    } else {
+                        Vector4::new(x, y * aspect, alt, 1.0)
+                    }
+                },
+                &SkyProjection::Panorama => Vector4::new(az / PI - 1.0, alt / (PI / 4.0) - 1.0, alt, 1.0)
+            })
+        })
+    }
+}
+
+fn horizontal_alt_az(h: &Vector3<f32>) -> (f32, f32)
+{
+    let len = (h.x * h.x + h.y * h.y + h.z * h.z).sqrt();
+    let alt = (h.y / len).max(-1.0).min(1.0).asin();
+    let az = h.x.atan2(-h.z);
+    (alt, if az < 0.0 { az + 2.0 * PI } else { az })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use catalog::equatorial_to_vector;
+
+    fn degrees(radians: f32) -> f32
+    {
+        radians.to_degrees()
+    }
+
+    #[test]
+    fn sidereal_time_at_j2000()
+    {
+        // 18h 41m 50.548s.
+        assert!((degrees(greenwich_sidereal_time(2451545.0)) - 280.46062).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sidereal_time_west_of_greenwich()
+    {
+        // Meeus, Astronomical Algorithms, example 12.b: 1987 April 10, 19:21 UT.
+        let jd = julian_date(1987, 4, 10, 19.35);
+        assert!((jd - 2446896.30625).abs() < 1e-6);
+        assert!((degrees(greenwich_sidereal_time(jd)) - 128.73787).abs() < 1e-3);
+        // 150 degrees west takes the local time past 0, which must wrap around to 338.7 degrees.
+        let lst = degrees(Sky::new(0.0, -150.0, jd).local_sidereal_time());
+        assert!((lst - 338.73787).abs() < 1e-3);
+    }
+
+    #[test]
+    fn altitude_and_azimuth_of_a_known_position()
+    {
+        // Meeus example 13.b: Venus from the US Naval Observatory (38 55' 17" N, 77 03' 56" W) at
+        // the time above, at right ascension 23h 09m 16.641s and declination -6 43' 11.61". It is
+        // 15.1249 degrees up, 68.0337 degrees west of south.
+        let sky = Sky::new(38.921389, -77.065556, julian_date(1987, 4, 10, 19.35));
+        let venus = equatorial_to_vector(347.3193375f32.to_radians(), -6.719892f32.to_radians());
+        let (alt, az) = sky.alt_az(&venus);
+        assert!((degrees(alt) - 15.1249).abs() < 0.01);
+        assert!((degrees(az) - 248.0337).abs() < 0.01);
+    }
+
+    #[test]
+    fn polaris_stands_at_the_latitude()
+    {
+        let polaris = equatorial_to_vector(37.95456f32.to_radians(), 89.26411f32.to_radians());
+        for &latitude in [-10.0, 20.0, 51.5, 70.0].iter() {
+            let sky = Sky::new(latitude, -120.0, 2460000.25);
+            let (alt, _) = sky.alt_az(&polaris);
+            assert!((degrees(alt) - latitude).abs() < 0.8);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+extern crate nalgebra;
+extern crate rand;
+
+use buffer::DepthBuffer;
+use camera::{transform, perspective_divide};
+use render::{Patch, render};
+use Pixel;
+use nalgebra::{Vector3, Vector4, Matrix4, Norm};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use rand::distributions::{IndependentSample, Normal};
+use std::f32::consts::PI;
+
+pub struct Star
+{
+    pub position: Vector3<f32>,
+    /// Brightness on the astronomical scale: smaller is brighter, and each step of 1 is about 2.5
+    /// times dimmer. For stars that aren't at infinity, this is the magnitude seen from a distance
+    /// of 1.
+    pub magnitude: f32,
+    /// Surface temperature in kelvin, which decides the star's color.
+    pub temperature: f32,
+    /// Offset of the star's twinkle, so stars don't all flicker together.
+    pub phase: f32
+}
+
+impl Star
+{
+    pub fn color(&self) -> Pixel
+    {
+        temperature_to_color(self.temperature)
+    }
+}
+
+/// How generated stars are spread through space.
+pub enum Distribution
+{
+    /// Evenly through a ball around the origin.
+    Sphere(f32),
+    /// Evenly over the surface of a sphere, as on a sky dome.
+    Shell(f32),
+    /// A galaxy-like disk in the XZ plane with the given radius and thickness, denser towards the
+    /// middle.
+    Disk(f32, f32),
+    /// A clump around a center point, with the given spread.
+    Cluster(Vector3<f32>, f32)
+}
+
+/// A seeded, reproducible collection of stars. The same seed and the same calls to `generate`
+/// always produce the same stars.
+pub struct Starfield
+{
+    pub stars: Vec<Star>,
+    /// Range of magnitudes given to generated stars. Faint stars are far more common than bright
+    /// ones, as in the real sky.
+    pub brightest: f32,
+    pub faintest: f32,
+    /// Faintest magnitude, after distance is taken into account, that is still drawn. Brightness
+    /// ramps from 1 at `brightest` down to 0 here.
+    pub limit: f32,
+    /// How strongly brightness fades with distance from the eye. 1 is physically correct, 0 turns
+    /// it off (for stars on a sky dome).
+    pub falloff: f32,
+    /// How much stars twinkle, from 0 (steady) to 1.
+    pub twinkle: f32,
+    rng: XorShiftRng
+}
+
+impl Starfield
+{
+    pub fn new(seed: u32) -> Starfield
+    {
+        Starfield {
+            stars: Vec::new(),
+            brightest: -1.0,
+            faintest: 6.0,
+            limit: 6.0,
+            falloff: 1.0,
+            twinkle: 0.3,
+            rng: XorShiftRng::from_seed([0x193a6754, seed ^ 0xa8a7d469, 0x97830e05, seed.rotate_left(16) ^ 0x113ba7bb])
+        }
+    }
+    fn unit_vector(&mut self) -> Vector3<f32>
+    {
+        let z = self.rng.gen_range(-1.0f32, 1.0);
+        let angle = self.rng.gen_range(0.0f32, 2.0*PI);
+        let r = (1.0 - z*z).max(0.0).sqrt();
+        Vector3::new(r*angle.cos(), r*angle.sin(), z)
+    }
+    fn gaussian(&mut self, spread: f32) -> f32
+    {
+        Normal::new(0.0, spread as f64).ind_sample(&mut self.rng) as f32
+    }
+    /// Adds `count` stars spread according to `distribution`.
+    pub fn generate(&mut self, distribution: &Distribution, count: usize) -> ()
+    {
+        for _ in 0..count {
+            let position = match distribution {
+                &Distribution::Sphere(radius) => {
+                    let r = radius * self.rng.gen::<f32>().cbrt();
+                    self.unit_vector() * r
+                },
+                &Distribution::Shell(radius) => self.unit_vector() * radius,
+                &Distribution::Disk(radius, thickness) => {
+                    // Exponential falloff from the middle, cut off at the edge.
+                    let r = loop {
+                        let r = -(1.0 - self.rng.gen::<f32>()).ln() * radius / 3.0;
+                        if r <= radius {
+                            break r;
+                        }
+                    };
+                    let angle = self.rng.gen_range(0.0f32, 2.0*PI);
+                    let y = self.gaussian(thickness / 2.0);
+                    Vector3::new(r*angle.cos(), y, r*angle.sin())
+                },
+                &Distribution::Cluster(center, spread) => {
+                    center + Vector3::new(self.gaussian(spread), self.gaussian(spread), self.gaussian(spread))
+                }
+            };
+            // The number of stars brighter than a magnitude grows about fourfold per magnitude.
+            let u = self.rng.gen::<f32>();
+            let range = self.faintest - self.brightest;
+            let magnitude = self.faintest + (u + (1.0 - u) * 10.0f32.powf(-0.6 * range)).log10() / 0.6;
+            // Cool red and orange stars are the most common.
+            let temperature = 2500.0 * 10.0f32.powf(self.rng.gen::<f32>().powi(3) * 1.3);
+            let phase = self.rng.gen_range(0.0f32, 2.0*PI);
+            self.stars.push(Star {
+                position: position,
+                magnitude: magnitude,
+                temperature: temperature,
+                phase: phase
+            });
+        }
+    }
+    /// How bright a star looks from `eye` at `time` seconds, from 0 to 1.
+    pub fn brightness(&self, star: &Star, eye: &Vector3<f32>, time: f32) -> f32
+    {
+        let distance = (star.position - *eye).norm().max(1e-3);
+        let apparent = star.magnitude + self.falloff * 5.0 * distance.log10();
+        let flicker = (0.5 + 0.5 * (time * 3.1 + star.phase).sin() * (time * 1.7 + 2.0 * star.phase).sin()) * self.twinkle;
+        magnitude_brightness(apparent, self.brightest, self.limit) * (1.0 - flicker)
+    }
+    /// Draws every star as a point, tinted by its temperature and scaled by its brightness. Stars
+    /// too dim to see are left out.
+    pub fn render(&self, buf: &mut DepthBuffer<Pixel>, view_projection: &Matrix4<f32>, eye: &Vector3<f32>, time: f32) -> ()
+    {
+        self.render_projected(buf, eye, time, |star| Some(transform(view_projection, &star.position)))
+    }
+    /// Like `render`, but `project` places each star in clip space, or returns `None` to leave it
+    /// out. Stars behind the viewer are left out too.
+    pub fn render_projected<P>(&self, buf: &mut DepthBuffer<Pixel>, eye: &Vector3<f32>, time: f32, project: P) -> ()
+        where P: Fn(&Star) -> Option<Vector4<f32>>
+    {
+        let mut positions = Vec::with_capacity(self.stars.len());
+        let mut colors = Vec::with_capacity(self.stars.len());
+        let mut patches = Vec::new();
+        for star in self.stars.iter() {
+            if let Some(p) = project(star).and_then(|p| perspective_divide(&p)) {
+                patches.push(Patch::Point(positions.len()));
+                positions.push(p);
+                colors.push(star.color() * self.brightness(star, eye, time));
+            }
+        }
+        let fragment = |_: &(), p: &Pixel| {
+            let (r, g, b) = p.rgb();
+            if r.max(g).max(b) > 1.0 / 255.0 {
+                Some(*p)
+            } else {
+                None
+            }
+        };
+        render(buf, &(), &positions, &colors, &patches, fragment)
+    }
+}
+
+/// Maps a magnitude onto a brightness from 1 at `brightest` down to 0 at `faintest`. Magnitudes
+/// already follow how the eye sees brightness, so the ramp is linear.
+pub fn magnitude_brightness(magnitude: f32, brightest: f32, faintest: f32) -> f32
+{
+    ((faintest - magnitude) / (faintest - brightest)).max(0.0).min(1.0)
+}
+
+/// The color of a black body at `kelvin` degrees, scaled so its brightest channel is 1.
+pub fn temperature_to_color(kelvin: f32) -> Pixel
+{
+    // Tanner Helland's curve fit to blackbody colors, good from about 1000K to 40000K.
+    let t = kelvin.max(1000.0).min(40000.0) / 100.0;
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.7 * (t - 60.0).powf(-0.1332)
+    };
+    let g = if t <= 66.0 {
+        99.47 * t.ln() - 161.12
+    } else {
+        288.12 * (t - 60.0).powf(-0.0755)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.52 * (t - 10.0).ln() - 305.04
+    };
+    let (r, g, b) = (r.max(0.0).min(255.0), g.max(0.0).min(255.0), b.max(0.0).min(255.0));
+    let top = r.max(g).max(b);
+    Pixel::Color(r / top, g / top, b / top)
+}
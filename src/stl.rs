@@ -0,0 +1,196 @@
+extern crate nalgebra;
+
+use mesh::{Mesh, MeshError};
+use render::Patch;
+use nalgebra::{Vector3, Norm, cross};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str;
+
+fn read_u32(data: &[u8], at: usize) -> u32
+{
+    data[at] as u32 | (data[at+1] as u32) << 8 | (data[at+2] as u32) << 16 | (data[at+3] as u32) << 24
+}
+
+fn read_f32(data: &[u8], at: usize) -> f32
+{
+    f32::from_bits(read_u32(data, at))
+}
+
+// STL stores a normal per facet, which is often left as zero. Fall back on the winding.
+fn facet(mesh: &mut Mesh, normal: Vector3<f32>, verts: [Vector3<f32>; 3]) -> ()
+{
+    let normal = if normal.norm_squared() > 0.0 {
+        normal.normalize()
+    } else {
+        let n = cross(&(verts[1] - verts[0]), &(verts[2] - verts[0]));
+        if n.norm_squared() > 0.0 { n.normalize() } else { n }
+    };
+    let base = mesh.positions.len();
+    for v in verts.iter() {
+        mesh.positions.push(*v);
+        mesh.normals.push(normal);
+    }
+    mesh.patches.push(Patch::Tri(base, base+1, base+2));
+}
+
+// The size of a binary STL file with `count` facets, if it fits in memory at all.
+fn binary_size(count: usize) -> Option<usize>
+{
+    count.checked_mul(50).and_then(|n| n.checked_add(84))
+}
+
+fn parse_binary(data: &[u8]) -> Result<Mesh, MeshError>
+{
+    let count = read_u32(data, 80) as usize;
+    match binary_size(count) {
+        Some(size) if size <= data.len() => (),
+        Some(_) => return Err(MeshError::Format("binary STL is truncated".to_string())),
+        None => return Err(MeshError::Format(format!("binary STL has too many facets ({})", count)))
+    }
+    let mut mesh = Mesh::new();
+    for i in 0..count {
+        let at = 84 + 50*i;
+        let vector = |j: usize| {
+            let at = at + 12*j;
+            Vector3::new(read_f32(data, at), read_f32(data, at+4), read_f32(data, at+8))
+        };
+        facet(&mut mesh, vector(0), [vector(1), vector(2), vector(3)]);
+    }
+    Ok(mesh)
+}
+
+fn parse_ascii(text: &str) -> Result<Mesh, MeshError>
+{
+    let mut mesh = Mesh::new();
+    let mut normal = Vector3::new(0.0, 0.0, 0.0);
+    let mut verts = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) => k,
+            None => continue
+        };
+        let vector = |words: &mut str::SplitWhitespace| {
+            let mut v = [0.0; 3];
+            for i in 0..3 {
+                v[i] = match words.next().map(|w| w.parse()) {
+                    Some(Ok(x)) => x,
+                    _ => return Err(MeshError::Parse(number, "expected three numbers".to_string()))
+                };
+            }
+            Ok(Vector3::new(v[0], v[1], v[2]))
+        };
+        match keyword {
+            "facet" => {
+                if words.next() != Some("normal") {
+                    return Err(MeshError::Parse(number, "expected 'facet normal'".to_string()));
+                }
+                normal = vector(&mut words)?;
+                verts.clear();
+            },
+            "vertex" => verts.push(vector(&mut words)?),
+            "endfacet" => {
+                if verts.len() != 3 {
+                    return Err(MeshError::Parse(number, format!("facet with {} vertices", verts.len())));
+                }
+                facet(&mut mesh, normal, [verts[0], verts[1], verts[2]]);
+            },
+            _ => {}
+        }
+    }
+    Ok(mesh)
+}
+
+/// Parses binary or ASCII STL data. STL doesn't share vertices between facets, so every triangle
+/// gets three vertices of its own, with the facet normal.
+pub fn parse_stl(data: &[u8]) -> Result<Mesh, MeshError>
+{
+    // Binary files can start with "solid" too, so trust the size first.
+    if data.len() >= 84 {
+        let count = read_u32(data, 80) as usize;
+        if binary_size(count) == Some(data.len()) {
+            return parse_binary(data);
+        }
+    }
+    if data.starts_with(b"solid") {
+        match str::from_utf8(data) {
+            Ok(text) => parse_ascii(text),
+            Err(_) => Err(MeshError::Format("ASCII STL is not valid UTF-8".to_string()))
+        }
+    } else {
+        Err(MeshError::Format("not an STL file, or truncated".to_string()))
+    }
+}
+
+pub fn load_stl<P: AsRef<Path>>(path: P) -> Result<Mesh, MeshError>
+{
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    parse_stl(&data)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn binary(facets: usize, header: &[u8]) -> Vec<u8>
+    {
+        let mut data = vec![0u8; 80];
+        data[..header.len()].copy_from_slice(header);
+        data.extend_from_slice(&(facets as u32).to_le_bytes());
+        for _ in 0..facets {
+            let values = [0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+            for v in values.iter() {
+                data.extend_from_slice(&v.to_bits().to_le_bytes());
+            }
+            data.extend_from_slice(&[0, 0]);
+        }
+        data
+    }
+
+    fn is_format_error(data: &[u8]) -> bool
+    {
+        match parse_stl(data) {
+            Err(MeshError::Format(_)) => true,
+            _ => false
+        }
+    }
+
+    #[test]
+    fn binary_is_detected_by_size_even_when_it_starts_with_solid()
+    {
+        let mesh = parse_stl(&binary(2, b"solid exported")).unwrap();
+        assert_eq!(mesh.patches.len(), 2);
+        assert_eq!(mesh.positions[1], Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(mesh.normals[0], Vector3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn ascii_facets_get_their_winding_normal_when_left_zero()
+    {
+        let data = "solid x\nfacet normal 0 0 0\nouter loop\nvertex 0 0 0\nvertex 0 1 0\nvertex 1 0 0\nendloop\nendfacet\nendsolid x\n";
+        let mesh = parse_stl(data.as_bytes()).unwrap();
+        assert_eq!(mesh.patches.len(), 1);
+        assert_eq!(mesh.normals[0], Vector3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn truncated_or_malformed_files_are_errors()
+    {
+        let mut data = binary(2, b"");
+        data.pop();
+        assert!(is_format_error(&data));
+        assert!(is_format_error(&binary(0, b"")[..40]));
+        let mut huge = binary(0, b"");
+        huge[80..84].copy_from_slice(&[0xff; 4]);
+        assert!(is_format_error(&huge));
+        match parse_stl(b"solid x\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\nendfacet\n") {
+            Err(MeshError::Parse(7, _)) => (),
+            _ => panic!("expected a parse error on line 7")
+        }
+    }
+}
@@ -1,4 +1,8 @@
+extern crate nalgebra;
+
 use buffer::Buffer;
+use camera::*;
+use nalgebra::Matrix4;
 
 
 #[derive(Copy,Clone)]
@@ -53,3 +57,118 @@ impl GridPrint for Buffer<ColorChar>
         self.get(x,y).clone()
     }
 }
+
+/// The ways a buffer can be laid out on terminal cells.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Presenter {
+    /// One pixel per cell.
+    FullCell,
+    /// Two pixels stacked in each cell, drawn with '▀' in two colors. See `HalfBlocks`.
+    HalfBlock,
+    /// 2x2 pixels per cell, drawn with the quadrant blocks of `grid_cell`.
+    Quadrant,
+    /// 2x4 dots per cell, drawn with braille characters. See `BrailleDots`.
+    Braille
+}
+
+impl Presenter {
+    /// Pixels per cell, as (columns, rows).
+    pub fn pixels_per_cell(&self) -> (usize, usize) {
+        match *self {
+            Presenter::FullCell => (1, 1),
+            Presenter::HalfBlock => (1, 2),
+            Presenter::Quadrant => (2, 2),
+            Presenter::Braille => (2, 4)
+        }
+    }
+}
+
+/// A presenter together with the shape of the terminal's cells. This determines the shape of a
+/// pixel, which projections need to know for geometry to come out undistorted.
+#[derive(Copy, Clone, Debug)]
+pub struct Display {
+    pub presenter: Presenter,
+    /// Width of a character cell divided by its height.
+    pub cell_aspect: f32
+}
+
+impl Display {
+    pub fn new(presenter: Presenter) -> Display {
+        Display {
+            presenter: presenter,
+            cell_aspect: CELL_ASPECT
+        }
+    }
+
+    /// Width of a pixel divided by its height.
+    pub fn pixel_aspect(&self) -> f32 {
+        let (columns, rows) = self.presenter.pixels_per_cell();
+        self.cell_aspect * rows as f32 / columns as f32
+    }
+
+    /// Size of the buffer that fills `columns` x `rows` cells.
+    pub fn buffer_size(&self, columns: usize, rows: usize) -> (usize, usize) {
+        let (x, y) = self.presenter.pixels_per_cell();
+        (columns * x, rows * y)
+    }
+
+    /// The displayed aspect ratio (width over height) of a `width` x `height` buffer.
+    pub fn aspect(&self, width: usize, height: usize) -> f32 {
+        viewport_aspect(width, height, self.pixel_aspect())
+    }
+
+    /// `camera::perspective`, corrected for this display.
+    pub fn perspective(&self, width: usize, height: usize, fovy: f32, near: f32, far: f32) -> Matrix4<f32> {
+        perspective(fovy, self.aspect(width, height), near, far)
+    }
+
+    /// `camera::orthographic`, corrected for this display.
+    pub fn orthographic(&self, width: usize, height: usize, view_height: f32, near: f32, far: f32) -> Matrix4<f32> {
+        orthographic(view_height, self.aspect(width, height), near, far)
+    }
+}
+
+/// Shows a buffer of 256-color palette indices two pixels to a cell.
+pub struct HalfBlocks<'a>(pub &'a Buffer<u8>);
+
+impl <'a> GridPrint for HalfBlocks<'a>
+{
+    fn get_size(&self) -> (usize, usize) {
+        (self.0.width, (self.0.height + 1) / 2)
+    }
+
+    fn get_cell(&self, x: usize, y: usize) -> ColorChar {
+        let buf = self.0;
+        // Buffer rows count up from the bottom, cells count down from the top.
+        let top = buf.height - 1 - 2*y;
+        let bottom = if top > 0 { *buf.get(x, top - 1) } else { 0 };
+        ColorChar(*buf.get(x, top), bottom, '▀')
+    }
+}
+
+/// Shows a buffer of on/off dots eight to a cell, using braille characters.
+pub struct BrailleDots<'a>(pub &'a Buffer<bool>);
+
+// Bit for each dot of a braille cell, indexed by [row from the top][column].
+static BRAILLE_BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+impl <'a> GridPrint for BrailleDots<'a>
+{
+    fn get_size(&self) -> (usize, usize) {
+        ((self.0.width + 1) / 2, (self.0.height + 3) / 4)
+    }
+
+    fn get_cell(&self, x: usize, y: usize) -> ColorChar {
+        let buf = self.0;
+        let mut bits = 0;
+        for row in 0..4 {
+            for column in 0..2 {
+                let (px, py) = (2*x + column, 4*y + row);
+                if px < buf.width && py < buf.height && *buf.get(px, buf.height - 1 - py) {
+                    bits |= BRAILLE_BITS[row][column];
+                }
+            }
+        }
+        ColorChar(7, 0, ::std::char::from_u32(0x2800 + bits).unwrap_or(' '))
+    }
+}
@@ -0,0 +1,372 @@
+extern crate nalgebra;
+
+use buffer::{Buffer, DepthBuffer};
+use render::Varying;
+use nalgebra::Vector2;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter
+{
+    Nearest,
+    Bilinear
+}
+
+/// What to do with texture coordinates outside of [0, 1].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wrap
+{
+    Repeat,
+    Clamp,
+    Mirror,
+    /// Use the sampler's border value.
+    Border
+}
+
+/// Wraps texel index `i` into `0..size`, or returns `None` if it falls on the border.
+fn wrap(mode: Wrap, i: i32, size: usize) -> Option<usize>
+{
+    let n = size as i32;
+    match mode {
+        Wrap::Repeat => Some(i.rem_euclid(n) as usize),
+        Wrap::Clamp => Some(i.max(0).min(n - 1) as usize),
+        Wrap::Mirror => {
+            let m = i.rem_euclid(2*n);
+            Some(if m < n { m } else { 2*n - 1 - m } as usize)
+        },
+        Wrap::Border => if 0 <= i && i < n { Some(i as usize) } else { None }
+    }
+}
+
+/// A texture backed by a `Buffer`. Texture coordinates run from (0, 0) at the corner of texel
+/// (0, 0) to (1, 1) at the far corner of the last texel, so v increases with y just like the
+/// buffer itself.
+pub struct Texture<T>
+{
+    pub buffer: Buffer<T>
+}
+
+impl <T> Texture<T>
+{
+    pub fn new(buffer: Buffer<T>) -> Texture<T>
+    {
+        Texture {
+            buffer: buffer
+        }
+    }
+    pub fn width(&self) -> usize
+    {
+        self.buffer.width
+    }
+    pub fn height(&self) -> usize
+    {
+        self.buffer.height
+    }
+}
+
+/// Anything a `Sampler` can read texels from.
+pub trait Texels<T>
+{
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    fn texel(&self, x: usize, y: usize) -> T;
+}
+
+impl <T: Clone> Texels<T> for Texture<T>
+{
+    fn width(&self) -> usize
+    {
+        self.buffer.width
+    }
+    fn height(&self) -> usize
+    {
+        self.buffer.height
+    }
+    fn texel(&self, x: usize, y: usize) -> T
+    {
+        self.buffer.get(x, y).clone()
+    }
+}
+
+/// Borrows the colors of a rendered `DepthBuffer` as a texture, so a finished pass can be sampled
+/// in a later one without copying. Pixels that were never drawn read as `background`.
+pub struct ColorView<'a, T: 'a>
+{
+    pub buffer: &'a DepthBuffer<T>,
+    pub background: T
+}
+
+/// Borrows the depths of a rendered `DepthBuffer` as a texture of `f32`. Pixels that were never
+/// drawn read as `clear`.
+pub struct DepthView<'a, T: 'a>
+{
+    pub buffer: &'a DepthBuffer<T>,
+    pub clear: f32
+}
+
+impl <'a, T: Clone> Texels<T> for ColorView<'a, T>
+{
+    fn width(&self) -> usize
+    {
+        self.buffer.width
+    }
+    fn height(&self) -> usize
+    {
+        self.buffer.height
+    }
+    fn texel(&self, x: usize, y: usize) -> T
+    {
+        match self.buffer.get(x, y) {
+            &Some((ref val, _)) => val.clone(),
+            &None => self.background.clone()
+        }
+    }
+}
+
+impl <'a, T> Texels<f32> for DepthView<'a, T>
+{
+    fn width(&self) -> usize
+    {
+        self.buffer.width
+    }
+    fn height(&self) -> usize
+    {
+        self.buffer.height
+    }
+    fn texel(&self, x: usize, y: usize) -> f32
+    {
+        match self.buffer.get(x, y) {
+            &Some((_, depth)) => depth,
+            &None => self.clear
+        }
+    }
+}
+
+impl <T> DepthBuffer<T>
+{
+    pub fn color_view<'a>(&'a self, background: T) -> ColorView<'a, T>
+    {
+        ColorView {
+            buffer: self,
+            background: background
+        }
+    }
+    pub fn depth_view<'a>(&'a self, clear: f32) -> DepthView<'a, T>
+    {
+        DepthView {
+            buffer: self,
+            clear: clear
+        }
+    }
+}
+
+impl <T: Clone> DepthBuffer<T>
+{
+    /// Copies the colors out into a texture, e.g. to build a `Mipmap` from them.
+    pub fn to_texture(&self, background: T) -> Texture<T>
+    {
+        let view = self.color_view(background.clone());
+        let mut buf = Buffer::new(self.width, self.height, background);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                buf.set(x, y, view.texel(x, y));
+            }
+        }
+        Texture::new(buf)
+    }
+    /// Copies the depths out into a texture.
+    pub fn to_depth_texture(&self, clear: f32) -> Texture<f32>
+    {
+        let view = self.depth_view(clear);
+        let mut buf = Buffer::new(self.width, self.height, clear);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                buf.set(x, y, view.texel(x, y));
+            }
+        }
+        Texture::new(buf)
+    }
+}
+
+/// A texture together with its chain of mip levels, each half the size of the one before, down
+/// to a single texel.
+pub struct Mipmap<T>
+{
+    pub levels: Vec<Texture<T>>
+}
+
+impl <T: Varying + Clone> Mipmap<T>
+{
+    /// Generates the mip chain by box filtering `base`. Each level is half the size of the last,
+    /// rounded down; odd sizes are filtered with three taps so that every texel contributes.
+    pub fn new(base: Texture<T>) -> Mipmap<T>
+    {
+        let mut levels = vec![base];
+        loop {
+            let next = {
+                let prev = &levels[levels.len()-1].buffer;
+                if (prev.width <= 1 && prev.height <= 1) || prev.width == 0 || prev.height == 0 {
+                    break;
+                }
+                let (width, height) = ((prev.width/2).max(1), (prev.height/2).max(1));
+                let mut buf = Buffer::new(width, height, prev.get(0, 0).clone());
+                for y in 0..height {
+                    let rows = mip_taps(prev.height, y);
+                    for x in 0..width {
+                        let columns = mip_taps(prev.width, x);
+                        let mut samples = Vec::with_capacity(9);
+                        for &(j, wy) in rows.iter() {
+                            for &(i, wx) in columns.iter() {
+                                if wx*wy > 0.0 {
+                                    samples.push((wx*wy, prev.get(i, j)));
+                                }
+                            }
+                        }
+                        buf.set(x, y, T::combine(&samples));
+                    }
+                }
+                Texture::new(buf)
+            };
+            levels.push(next);
+        }
+        Mipmap {
+            levels: levels
+        }
+    }
+}
+
+// The texels along one axis of a level `size` texels long that make up texel `i` of the next
+// level, with their weights. An odd size of 2n+1 texels is split evenly between n texels, so the
+// filter spans three of them.
+fn mip_taps(size: usize, i: usize) -> [(usize, f32); 3]
+{
+    if size == 1 {
+        [(0, 1.0), (0, 0.0), (0, 0.0)]
+    } else if size % 2 == 0 {
+        [(2*i, 0.5), (2*i+1, 0.5), (2*i+1, 0.0)]
+    } else {
+        let n = (size/2) as f32;
+        let total = size as f32;
+        [(2*i, (n - i as f32)/total), (2*i+1, n/total), (2*i+2, (1.0 + i as f32)/total)]
+    }
+}
+
+pub struct Sampler<T>
+{
+    pub filter: Filter,
+    /// How to pick between mip levels. `Bilinear` blends the two nearest levels, which together
+    /// with bilinear `filter` gives trilinear filtering.
+    pub mip_filter: Filter,
+    /// Maximum number of samples taken along the long axis of the footprint when sampling with
+    /// gradients. 1 disables anisotropic filtering.
+    pub max_anisotropy: usize,
+    pub wrap_u: Wrap,
+    pub wrap_v: Wrap,
+    /// Returned for texels outside the texture when wrapping with `Wrap::Border`.
+    pub border: T
+}
+
+impl <T: Clone> Sampler<T>
+{
+    pub fn new(filter: Filter, wrap: Wrap, border: T) -> Sampler<T>
+    {
+        Sampler {
+            filter: filter,
+            mip_filter: filter,
+            max_anisotropy: 1,
+            wrap_u: wrap,
+            wrap_v: wrap,
+            border: border
+        }
+    }
+    /// Fetches a single texel, applying the wrap modes to out of range indices.
+    pub fn texel<S: Texels<T>>(&self, tex: &S, x: i32, y: i32) -> T
+    {
+        if tex.width() == 0 || tex.height() == 0 {
+            return self.border.clone();
+        }
+        match (wrap(self.wrap_u, x, tex.width()), wrap(self.wrap_v, y, tex.height())) {
+            (Some(x), Some(y)) => tex.texel(x, y),
+            _ => self.border.clone()
+        }
+    }
+    /// Samples the texel nearest to `uv`, regardless of the filter setting. Unlike `sample`, this
+    /// works for texel types that can't be blended.
+    pub fn nearest<S: Texels<T>>(&self, tex: &S, uv: &Vector2<f32>) -> T
+    {
+        let x = (uv.x * tex.width() as f32).floor() as i32;
+        let y = (uv.y * tex.height() as f32).floor() as i32;
+        self.texel(tex, x, y)
+    }
+}
+
+impl <T: Varying + Clone> Sampler<T>
+{
+    pub fn sample<S: Texels<T>>(&self, tex: &S, uv: &Vector2<f32>) -> T
+    {
+        match self.filter {
+            Filter::Nearest => self.nearest(tex, uv),
+            Filter::Bilinear => {
+                let x = uv.x * tex.width() as f32 - 0.5;
+                let y = uv.y * tex.height() as f32 - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+                let texels = [
+                    self.texel(tex, x0, y0),
+                    self.texel(tex, x0+1, y0),
+                    self.texel(tex, x0, y0+1),
+                    self.texel(tex, x0+1, y0+1)];
+                T::combine(&[
+                    ((1.0-fx)*(1.0-fy), &texels[0]),
+                    (fx*(1.0-fy), &texels[1]),
+                    ((1.0-fx)*fy, &texels[2]),
+                    (fx*fy, &texels[3])])
+            }
+        }
+    }
+    /// Samples `mip` at an explicit level of detail, 0 being the base texture.
+    pub fn sample_lod(&self, mip: &Mipmap<T>, uv: &Vector2<f32>, lod: f32) -> T
+    {
+        let last = mip.levels.len() - 1;
+        let lod = lod.max(0.0).min(last as f32);
+        match self.mip_filter {
+            Filter::Nearest => self.sample(&mip.levels[lod.round() as usize], uv),
+            Filter::Bilinear => {
+                let level = (lod.floor() as usize).min(last);
+                let t = lod - level as f32;
+                if level == last || t == 0.0 {
+                    return self.sample(&mip.levels[level], uv);
+                }
+                let (a, b) = (self.sample(&mip.levels[level], uv), self.sample(&mip.levels[level+1], uv));
+                T::combine(&[(1.0 - t, &a), (t, &b)])
+            }
+        }
+    }
+    /// Samples `mip`, choosing the level of detail from how far `uv` moves per pixel along x and
+    /// y. In a fragment shader these are usually taken from `FragmentInfo::dfdx` and `dfdy`.
+    pub fn sample_grad(&self, mip: &Mipmap<T>, uv: &Vector2<f32>, duvdx: &Vector2<f32>, duvdy: &Vector2<f32>) -> T
+    {
+        let base = &mip.levels[0];
+        let size = Vector2::new(base.width() as f32, base.height() as f32);
+        let dx = Vector2::new(duvdx.x*size.x, duvdx.y*size.y);
+        let dy = Vector2::new(duvdy.x*size.x, duvdy.y*size.y);
+        let (lx, ly) = (dx.x.hypot(dx.y), dy.x.hypot(dy.y));
+        let (major, long, short) = if lx >= ly { (duvdx, lx, ly) } else { (duvdy, ly, lx) };
+        let count = if short > 0.0 {
+            ((long / short).ceil() as usize).max(1).min(self.max_anisotropy.max(1))
+        } else {
+            self.max_anisotropy.max(1)
+        };
+        let lod = (long / count as f32).max(1e-6).log2();
+        if count == 1 {
+            return self.sample_lod(mip, uv, lod);
+        }
+        // Spread the samples evenly along the long axis of the footprint.
+        let samples: Vec<T> = (0..count).map(|i| {
+            let t = (i as f32 + 0.5) / count as f32 - 0.5;
+            self.sample_lod(mip, &(*uv + *major * t), lod)
+        }).collect();
+        let weight = 1.0 / count as f32;
+        T::combine(&samples.iter().map(|s| (weight, s)).collect::<Vec<_>>())
+    }
+}
@@ -0,0 +1,178 @@
+extern crate nalgebra;
+
+use render::Varying;
+use Pixel;
+use nalgebra::{Vector1, Vector2, Vector3, Vector4, Vector5, Vector6, Matrix2, Matrix3, Matrix4};
+use nalgebra::{Point1, Point2, Point3, Point4, Point5, Point6};
+
+/// Implements `Varying` for types that can be added together and scaled by an `f32`, blending them
+/// as a weighted sum.
+///
+/// ```no_run
+/// #[macro_use] extern crate starfield_render;
+/// use std::ops::{Add, Mul};
+///
+/// #[derive(Clone)]
+/// struct Fog(f32);
+/// impl Add for Fog { type Output = Fog; fn add(self, o: Fog) -> Fog { Fog(self.0 + o.0) } }
+/// impl Mul<f32> for Fog { type Output = Fog; fn mul(self, w: f32) -> Fog { Fog(self.0 * w) } }
+///
+/// impl_varying_ops!(Fog);
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! impl_varying_ops {
+    ($($t:ty),*) => {
+        $(
+            impl $crate::Varying for $t
+            {
+                fn combine(list: &[(f32, &Self)]) -> Self
+                {
+                    let mut acc = list[0].1.clone() * list[0].0;
+                    for i in 1..list.len() {
+                        acc = acc + list[i].1.clone() * list[i].0;
+                    }
+                    acc
+                }
+            }
+        )*
+    }
+}
+
+/// Implements `Varying` for a struct whose fields are all `Varying`, by blending each field on its
+/// own. Every field must be listed.
+///
+/// ```no_run
+/// #[macro_use] extern crate starfield_render;
+/// extern crate nalgebra;
+/// use starfield_render::{Flat, Pixel};
+/// use nalgebra::Vector2;
+///
+/// struct Attributes { uv: Vector2<f32>, color: Pixel, fog: f32, material: Flat<usize> }
+/// impl_varying!(Attributes { uv, color, fog, material });
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! impl_varying {
+    ($name:ident { $($field:ident),* }) => {
+        impl $crate::Varying for $name
+        {
+            fn combine(list: &[(f32, &Self)]) -> Self
+            {
+                $name {
+                    $(
+                        $field: $crate::combine_field(list, |v| &v.$field)
+                    ),*
+                }
+            }
+        }
+    }
+}
+
+/// Blends one field of a list of weighted values, as `impl_varying!` and the tuple and array
+/// impls do. Patches have at most three vertices, so the field list is built on the stack unless
+/// the list is unusually long.
+#[doc(hidden)]
+pub fn combine_field<S, T: Varying, F>(list: &[(f32, &S)], field: F) -> T where F: Fn(&S) -> &T
+{
+    const STACK: usize = 8;
+    if list.len() <= STACK {
+        let mut fields = [(0.0, field(list[0].1)); STACK];
+        for (slot, &(w, v)) in fields.iter_mut().zip(list) {
+            *slot = (w, field(v));
+        }
+        T::combine(&fields[..list.len()])
+    } else {
+        T::combine(&list.iter().map(|&(w, v)| (w, field(v))).collect::<Vec<_>>())
+    }
+}
+
+/// A varying that isn't interpolated: every fragment of a patch gets the value from the patch's
+/// first vertex (its provoking vertex). Use it for ids, material indices or face colors, alone or
+/// as a field of a struct using `impl_varying!`.
+///
+/// Derivatives of a flat value from `FragmentInfo` are meaningless.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Flat<T>(pub T);
+
+impl <T: Clone> Varying for Flat<T>
+{
+    fn combine(list: &[(f32, &Self)]) -> Self
+    {
+        list[0].1.clone()
+    }
+}
+
+impl_varying_ops!(f32, Pixel);
+impl_varying_ops!(Vector1<f32>, Vector2<f32>, Vector3<f32>, Vector4<f32>, Vector5<f32>, Vector6<f32>);
+impl_varying_ops!(Matrix2<f32>, Matrix3<f32>, Matrix4<f32>);
+
+impl Varying for f64
+{
+    fn combine(list: &[(f32, &Self)]) -> Self
+    {
+        list.iter().fold(0.0, |acc, &(w, v)| acc + *v * w as f64)
+    }
+}
+
+// Points can't be added to each other, only offset by vectors, so every point after the first is
+// added as a vector from the origin. The weights sum to 1, so this is the same affine blend.
+macro_rules! impl_varying_point {
+    ($($t:ident),*) => {
+        $(
+            impl Varying for $t<f32>
+            {
+                fn combine(list: &[(f32, &Self)]) -> Self
+                {
+                    let mut acc = *list[0].1 * list[0].0;
+                    for i in 1..list.len() {
+                        acc = acc + list[i].1.to_vector() * list[i].0;
+                    }
+                    acc
+                }
+            }
+        )*
+    }
+}
+
+impl_varying_point!(Point1, Point2, Point3, Point4, Point5, Point6);
+
+macro_rules! impl_varying_tuple {
+    ($($t:ident $i:tt),*) => {
+        impl <$($t: Varying),*> Varying for ($($t,)*)
+        {
+            fn combine(list: &[(f32, &Self)]) -> Self
+            {
+                ($(combine_field::<_, $t, _>(list, |v| &v.$i),)*)
+            }
+        }
+    }
+}
+
+impl_varying_tuple!(A 0);
+impl_varying_tuple!(A 0, B 1);
+impl_varying_tuple!(A 0, B 1, C 2);
+impl_varying_tuple!(A 0, B 1, C 2, D 3);
+impl_varying_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_varying_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+macro_rules! impl_varying_array {
+    ($n:expr; $($i:expr),*) => {
+        impl <T: Varying> Varying for [T; $n]
+        {
+            fn combine(list: &[(f32, &Self)]) -> Self
+            {
+                [$(combine_field::<_, T, _>(list, |v| &v[$i])),*]
+            }
+        }
+    }
+}
+
+impl_varying_array!(1; 0);
+impl_varying_array!(2; 0, 1);
+impl_varying_array!(3; 0, 1, 2);
+impl_varying_array!(4; 0, 1, 2, 3);
+impl_varying_array!(5; 0, 1, 2, 3, 4);
+impl_varying_array!(6; 0, 1, 2, 3, 4, 5);
+impl_varying_array!(7; 0, 1, 2, 3, 4, 5, 6);
+impl_varying_array!(8; 0, 1, 2, 3, 4, 5, 6, 7);
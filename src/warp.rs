@@ -0,0 +1,154 @@
+extern crate nalgebra;
+extern crate rand;
+
+use buffer::DepthBuffer;
+use camera::{transform, perspective_divide};
+use render::{Patch, Varying, render};
+use starfield::temperature_to_color;
+use Pixel;
+use nalgebra::{Vector3, Vector4, Matrix4};
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+/// The classic "flying through space" effect. Stars live in view space in front of the camera and
+/// stream towards it along +z; each is drawn as a streak as long as the distance it covers in
+/// `streak` seconds, brighter the closer it is. Stars that pass the camera are sent back to the far
+/// end.
+pub struct Warp
+{
+    pub positions: Vec<Vector3<f32>>,
+    pub colors: Vec<Pixel>,
+    /// Units per second.
+    pub speed: f32,
+    /// Seconds of travel shown by each streak. 0 draws plain points.
+    pub streak: f32,
+    /// Stars are spread from `near` to `depth` units in front of the camera, and up to `spread`
+    /// units to each side.
+    pub near: f32,
+    pub depth: f32,
+    pub spread: f32,
+    rng: XorShiftRng
+}
+
+impl Warp
+{
+    pub fn new(seed: u32, count: usize) -> Warp
+    {
+        let mut warp = Warp {
+            positions: Vec::with_capacity(count),
+            colors: Vec::with_capacity(count),
+            speed: 10.0,
+            streak: 0.1,
+            near: 0.5,
+            depth: 30.0,
+            spread: 10.0,
+            rng: XorShiftRng::from_seed([0x3c6ef372, seed ^ 0xbb67ae85, 0x510e527f, seed.rotate_left(16) ^ 0x9b05688c])
+        };
+        for _ in 0..count {
+            let z = if warp.depth > warp.near { warp.rng.gen_range(warp.near, warp.depth) } else { warp.near };
+            let position = warp.spawn(z);
+            warp.positions.push(position);
+            let color = temperature_to_color(warp.rng.gen_range(3000.0, 12000.0));
+            warp.colors.push(color);
+        }
+        warp
+    }
+    fn spawn(&mut self, distance: f32) -> Vector3<f32>
+    {
+        if self.spread > 0.0 {
+            Vector3::new(self.rng.gen_range(-self.spread, self.spread), self.rng.gen_range(-self.spread, self.spread), -distance)
+        } else {
+            Vector3::new(0.0, 0.0, -distance)
+        }
+    }
+    /// Moves the stars on by `dt` seconds, recycling any that have passed the near plane.
+    pub fn update(&mut self, dt: f32) -> ()
+    {
+        for i in 0..self.positions.len() {
+            let z = self.positions[i].z + self.speed * dt;
+            self.positions[i] = if z > -self.near {
+                // Keep the overshoot so stars don't bunch up at the far end.
+                let distance = if self.depth > self.near {
+                    self.depth - (z + self.near) % (self.depth - self.near)
+                } else {
+                    self.near
+                };
+                self.spawn(distance)
+            } else {
+                Vector3::new(self.positions[i].x, self.positions[i].y, z)
+            };
+        }
+    }
+    /// Draws the streaks through `projection`. Each streak fades from its head to its tail.
+    pub fn render(&self, buf: &mut DepthBuffer<Pixel>, projection: &Matrix4<f32>) -> ()
+    {
+        let mut positions = Vec::with_capacity(self.positions.len() * 2);
+        let mut colors = Vec::with_capacity(self.positions.len() * 2);
+        let mut patches = Vec::with_capacity(self.positions.len());
+        let length = self.speed * self.streak;
+        for (p, color) in self.positions.iter().zip(self.colors.iter()) {
+            let brightness = (1.0 - (-p.z - self.near) / (self.depth - self.near)).max(0.0).min(1.0);
+            let tail = Vector3::new(p.x, p.y, p.z - length);
+            let ends = (perspective_divide(&transform(projection, p)), perspective_divide(&transform(projection, &tail)));
+            let (head_pos, tail_pos) = match ends {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue
+            };
+            // Lines are only drawn with both ends on screen, and the streaks crossing the edge
+            // are the closest and brightest, so cut them down to the part that's in view.
+            let (t0, t1) = match clip_to_view(&head_pos, &tail_pos) {
+                Some(range) => range,
+                None => continue
+            };
+            let (head_color, tail_color) = (*color * (brightness * brightness), *color * (brightness * brightness * 0.25));
+            let head = positions.len();
+            for &t in [t0, t1].iter() {
+                positions.push(Vector4::combine(&[(1.0 - t, &head_pos), (t, &tail_pos)]));
+                colors.push(Pixel::combine(&[(1.0 - t, &head_color), (t, &tail_color)]));
+            }
+            patches.push(Patch::Line(head, head + 1));
+        }
+        render(buf, &(), &positions, &colors, &patches, |_: &(), p: &Pixel| Some(*p))
+    }
+}
+
+/// The part of the screen-space line from `a` to `b` inside the view, as a range of the fraction
+/// of the way along it, or `None` if it misses the view.
+fn clip_to_view(a: &Vector4<f32>, b: &Vector4<f32>) -> Option<(f32, f32)>
+{
+    // The last row and column start a pixel's worth of rounding short of 1.
+    let edge = 1.0 - 1e-4;
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for &(start, delta) in [(a.x, b.x - a.x), (a.y, b.y - a.y)].iter() {
+        if delta == 0.0 {
+            if start < -1.0 || start > edge {
+                return None;
+            }
+            continue;
+        }
+        let (enter, exit) = ((-1.0 - start) / delta, (edge - start) / delta);
+        let (enter, exit) = if delta > 0.0 { (enter, exit) } else { (exit, enter) };
+        t0 = t0.max(enter);
+        t1 = t1.min(exit);
+    }
+    if t0 <= t1 {
+        Some((t0, t1))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn degenerate_ranges_do_not_panic()
+    {
+        let mut warp = Warp::new(1, 4);
+        warp.spread = 0.0;
+        warp.depth = warp.near;
+        warp.update(10.0);
+        assert!(warp.positions.iter().all(|p| p.x == 0.0 && p.y == 0.0 && p.z == -warp.near));
+    }
+}
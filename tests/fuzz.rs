@@ -0,0 +1,140 @@
+extern crate starfield_render;
+extern crate nalgebra;
+extern crate rand;
+
+use starfield_render as sf;
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::f32;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use nalgebra::Vector4;
+
+// Coordinates chosen to hit the rasterizer's edge cases: exact zeros and repeats, huge and tiny
+// magnitudes, and values that aren't numbers at all.
+fn coordinate(rng: &mut XorShiftRng) -> f32
+{
+    match rng.gen_range(0, 12) {
+        0 => 0.0,
+        1 => 1.0,
+        2 => -1.0,
+        3 => f32::NAN,
+        4 => f32::INFINITY,
+        5 => f32::NEG_INFINITY,
+        6 => rng.gen_range(-1e30, 1e30),
+        7 => rng.gen_range(-1e-30, 1e-30),
+        8 => f32::MIN_POSITIVE / 4.0,
+        _ => rng.gen_range(-2.0, 2.0)
+    }
+}
+
+fn vertex(rng: &mut XorShiftRng, previous: &[Vector4<f32>]) -> Vector4<f32>
+{
+    if !previous.is_empty() && rng.gen_weighted_bool(4) {
+        // Reuse an earlier vertex, exactly or scaled, to make zero-area triangles.
+        let v = previous[rng.gen_range(0, previous.len())];
+        return if rng.gen() { v } else { v * rng.gen_range(-3.0, 3.0) };
+    }
+    if rng.gen_weighted_bool(3) {
+        Vector4::new(coordinate(rng), coordinate(rng), coordinate(rng), coordinate(rng))
+    } else {
+        Vector4::new(rng.gen_range(-1.5, 1.5), rng.gen_range(-1.5, 1.5), rng.gen_range(-1.0, 1.0), rng.gen_range(-0.5, 2.0))
+    }
+}
+
+fn patch(rng: &mut XorShiftRng, count: usize) -> sf::Patch
+{
+    match rng.gen_range(0, 6) {
+        0 => sf::Patch::Point(rng.gen_range(0, count)),
+        1 => sf::Patch::Line(rng.gen_range(0, count), rng.gen_range(0, count)),
+        _ => sf::Patch::Tri(rng.gen_range(0, count), rng.gen_range(0, count), rng.gen_range(0, count))
+    }
+}
+
+fn run(seed: u32, rounds: usize) -> usize
+{
+    let mut rng = XorShiftRng::from_seed([seed, 0x6a09e667, 0xbb67ae85, 0x3c6ef372]);
+    let mut buffer: sf::DepthBuffer<f32> = sf::Buffer::new(64, 32, None);
+    let mut tiling = sf::Tiling::default();
+    tiling.tile_height = 5;
+    let plain = |_: &(), v: &f32| Some(*v);
+    let with_info = sf::WithInfo(|_: &(), v: &f32, info: &sf::FragmentInfo<f32>| {
+        Some(*v + info.dfdx() + info.dfdy() + info.depth)
+    });
+    let mut drawn = 0;
+    for round in 0..rounds {
+        let mut positions = Vec::new();
+        for _ in 0..rng.gen_range(1, 12) {
+            let v = vertex(&mut rng, &positions);
+            positions.push(v);
+        }
+        let varying: Vec<f32> = (0..positions.len()).map(|i| i as f32).collect();
+        let patches: Vec<sf::Patch> = (0..rng.gen_range(1, 8)).map(|_| patch(&mut rng, positions.len())).collect();
+
+        buffer.clear();
+        sf::render(&mut buffer, &(), &positions, &varying, &patches, &plain);
+        sf::render_with(&mut buffer, &(), &positions, &varying, &patches, sf::Cull::None, sf::Degenerate::Lines, &with_info);
+        tiling.degenerate = if round % 2 == 0 { sf::Degenerate::Skip } else { sf::Degenerate::Lines };
+        sf::render_tiled(&mut buffer, &tiling, &(), &positions, &varying, &patches, &plain);
+        let _ = sf::try_render(&mut buffer, &(), &positions, &varying, &patches, &plain);
+        for y in 0..buffer.height {
+            drawn += buffer.row_iter(y).filter(|p| p.is_some()).count();
+        }
+    }
+    drawn
+}
+
+// Feeds the rasterizer random vertices and patches. A hang shows up as the worker missing its
+// deadline; a panic as it hanging up.
+fn fuzz(seed: u32, rounds: usize) -> ()
+{
+    let (send, receive) = mpsc::channel();
+    thread::spawn(move || {
+        let drawn = run(seed, rounds);
+        send.send(drawn).unwrap();
+    });
+    match receive.recv_timeout(Duration::from_secs(120)) {
+        Ok(_) => (),
+        Err(mpsc::RecvTimeoutError::Timeout) => panic!("seed {}: timed out, the rasterizer may be stuck", seed),
+        Err(mpsc::RecvTimeoutError::Disconnected) => panic!("seed {}: the rasterizer panicked", seed)
+    }
+}
+
+#[test]
+fn fuzz_seed_1()
+{
+    fuzz(1, 2000);
+}
+
+#[test]
+fn fuzz_seed_2()
+{
+    fuzz(2, 2000);
+}
+
+#[test]
+fn fuzz_seed_deadbeef()
+{
+    fuzz(0xdeadbeef, 2000);
+}
+
+// Outlines of collapsed triangles keep the triangle's first vertex as the provoking vertex on
+// every edge, so flat varyings don't change along them.
+#[test]
+fn degenerate_outline_keeps_provoking_vertex()
+{
+    let mut buffer: sf::DepthBuffer<u32> = sf::Buffer::new(32, 16, None);
+    let positions = vec![Vector4::new(-0.5, -0.5, 0.0, 1.0), Vector4::new(0.5, 0.5, 0.0, 1.0), Vector4::new(0.0, 0.0, 0.0, 1.0)];
+    let varying = vec![sf::Flat(0u32), sf::Flat(1), sf::Flat(2)];
+    let patches = vec![sf::Patch::Tri(0, 1, 2)];
+    let seen = RefCell::new(HashSet::new());
+    let fragment = |_: &(), v: &sf::Flat<u32>| {
+        seen.borrow_mut().insert(v.0);
+        Some(v.0)
+    };
+    sf::render_with(&mut buffer, &(), &positions, &varying, &patches, sf::Cull::Back, sf::Degenerate::Lines, &fragment);
+    assert_eq!(seen.into_inner().into_iter().collect::<Vec<_>>(), vec![0]);
+}